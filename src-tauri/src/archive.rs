@@ -0,0 +1,149 @@
+//! Download archive
+//!
+//! A text file of `extractor id` lines in the same format as yt-dlp's
+//! `--download-archive`, so yt-dlp records finished downloads itself and the
+//! app can skip archived videos before they are queued.
+
+use regex::Regex;
+use std::collections::HashSet;
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, OnceLock};
+
+pub struct ArchiveStore {
+    path: PathBuf,
+    /// Serializes rewrites of the archive file
+    write_lock: Mutex<()>,
+}
+
+impl ArchiveStore {
+    pub fn new(app_data_dir: PathBuf) -> Self {
+        Self {
+            path: app_data_dir.join("archive.txt"),
+            write_lock: Mutex::new(()),
+        }
+    }
+
+    /// Path passed to yt-dlp's `--download-archive`
+    pub fn get_path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Archive key for an extractor and video ID, e.g. `youtube dQw4w9WgXcQ`
+    pub fn key(extractor: &str, id: &str) -> String {
+        format!("{} {}", extractor.to_lowercase(), id)
+    }
+
+    /// Archive key for a YouTube video URL, without querying yt-dlp
+    pub fn key_for_url(url: &str) -> Option<String> {
+        static VIDEO_ID_REGEX: OnceLock<Regex> = OnceLock::new();
+        let regex = VIDEO_ID_REGEX.get_or_init(|| {
+            Regex::new(r"(?:youtube\.com/(?:watch\?(?:.*&)?v=|shorts/|live/|embed/)|youtu\.be/)([\w-]{11})")
+                .unwrap()
+        });
+
+        regex
+            .captures(url)
+            .map(|caps| Self::key("youtube", &caps[1]))
+    }
+
+    /// All entries, in the order they were recorded
+    pub fn entries(&self) -> Vec<String> {
+        let content = fs::read_to_string(&self.path).unwrap_or_default();
+        let mut seen = HashSet::new();
+        content
+            .lines()
+            .filter_map(Self::normalize)
+            .filter(|entry| seen.insert(entry.clone()))
+            .collect()
+    }
+
+    pub fn contains(&self, key: &str) -> bool {
+        let content = fs::read_to_string(&self.path).unwrap_or_default();
+        content
+            .lines()
+            .any(|line| Self::normalize(line).as_deref() == Some(key))
+    }
+
+    /// Merge entries from another archive file, returns how many were new
+    pub fn import(&self, source: &Path) -> Result<usize, String> {
+        let content = fs::read_to_string(source)
+            .map_err(|e| format!("Failed to read archive file: {}", e))?;
+
+        let _guard = self.write_lock.lock().unwrap();
+        let existing: HashSet<String> = self.entries().into_iter().collect();
+        let mut added = HashSet::new();
+        let new_entries: Vec<String> = content
+            .lines()
+            .filter_map(Self::normalize)
+            .filter(|entry| !existing.contains(entry) && added.insert(entry.clone()))
+            .collect();
+
+        if new_entries.is_empty() {
+            return Ok(0);
+        }
+
+        if let Some(parent) = self.path.parent() {
+            fs::create_dir_all(parent)
+                .map_err(|e| format!("Failed to create archive directory: {}", e))?;
+        }
+        let mut file = fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .map_err(|e| format!("Failed to open archive file: {}", e))?;
+        for entry in &new_entries {
+            writeln!(file, "{}", entry)
+                .map_err(|e| format!("Failed to write archive file: {}", e))?;
+        }
+
+        Ok(new_entries.len())
+    }
+
+    /// Write the archive to another file
+    pub fn export(&self, destination: &Path) -> Result<(), String> {
+        let mut content = self.entries().join("\n");
+        content.push('\n');
+        fs::write(destination, content).map_err(|e| format!("Failed to export archive: {}", e))
+    }
+
+    /// Remove entries so those videos can be downloaded again, returns how many were removed
+    pub fn prune(&self, entries: &[String]) -> Result<usize, String> {
+        let remove: HashSet<String> = entries.iter().filter_map(|e| Self::normalize(e)).collect();
+
+        let _guard = self.write_lock.lock().unwrap();
+        let current = self.entries();
+        let kept: Vec<&String> = current.iter().filter(|e| !remove.contains(*e)).collect();
+        let removed = current.len() - kept.len();
+        if removed == 0 {
+            return Ok(0);
+        }
+
+        let mut content = kept
+            .iter()
+            .map(|e| e.as_str())
+            .collect::<Vec<_>>()
+            .join("\n");
+        content.push('\n');
+
+        let temp_path = self.path.with_extension("txt.tmp");
+        fs::write(&temp_path, content)
+            .map_err(|e| format!("Failed to write archive file: {}", e))?;
+        fs::rename(&temp_path, &self.path)
+            .map_err(|e| format!("Failed to replace archive file: {}", e))?;
+
+        Ok(removed)
+    }
+
+    /// Trim a line and check it has the `extractor id` shape
+    fn normalize(line: &str) -> Option<String> {
+        let mut parts = line.split_whitespace();
+        let extractor = parts.next()?;
+        let id = parts.next()?;
+        if parts.next().is_some() {
+            return None;
+        }
+        Some(Self::key(extractor, id))
+    }
+}
@@ -0,0 +1,229 @@
+//! Temporary download files
+//!
+//! While downloading, yt-dlp leaves `.part`, `.part-FragN` and `.ytdl` files
+//! next to each destination, aria2c adds `.aria2` control files and
+//! post-processors write `.temp.<ext>` files. This app adds
+//! `.{task_id}.info.json` and `.{task_id}.filepath` sidecars in the download
+//! directory. Tasks track the files they create so cancelling can remove
+//! them. Removed tasks hand their files to `LeftoverStore`, and
+//! `find_orphans` looks for leftovers of those and of sidecars no known task
+//! owns. Files this app didn't write are never considered.
+
+use crate::models::DownloadTask;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime};
+use uuid::Uuid;
+
+/// Files modified this recently may belong to a download that is just starting
+const RECENT: Duration = Duration::from_secs(60);
+
+/// Destination file announced on a yt-dlp output line
+pub fn destination(line: &str) -> Option<PathBuf> {
+    let path = if let Some(rest) = line.strip_prefix("[download] Destination: ") {
+        rest
+    } else if let Some(rest) = line.strip_prefix("[Merger] Merging formats into ") {
+        rest.trim_matches('"')
+    } else {
+        return None;
+    };
+    let path = path.trim();
+    (!path.is_empty()).then(|| PathBuf::from(path))
+}
+
+/// The `.info.json` and `.filepath` sidecars written for a task
+pub fn sidecars(download_dir: &Path, task_id: &str) -> [PathBuf; 2] {
+    [
+        download_dir.join(format!(".{}.info.json", task_id)),
+        download_dir.join(format!(".{}.filepath", task_id)),
+    ]
+}
+
+/// Task ID of a sidecar file name
+fn sidecar_task_id(name: &str) -> Option<&str> {
+    let rest = name.strip_prefix('.')?;
+    let id = rest
+        .strip_suffix(".info.json")
+        .or_else(|| rest.strip_suffix(".filepath"))?;
+    Uuid::parse_str(id).is_ok().then_some(id)
+}
+
+/// Whether `name` is a file yt-dlp, aria2c or a post-processor writes while
+/// producing the file named `base`
+fn is_partial_of(name: &str, base: &str) -> bool {
+    if let Some(suffix) = name.strip_prefix(base) {
+        if matches!(suffix, ".part" | ".ytdl" | ".aria2" | ".part.aria2")
+            || suffix.starts_with(".part-Frag")
+        {
+            return true;
+        }
+    }
+    // Post-processors write "name.temp.ext" and rename it over "name.ext"
+    match base.rsplit_once('.') {
+        Some((stem, ext)) => name == format!("{}.temp.{}", stem, ext),
+        None => false,
+    }
+}
+
+/// Partial files next to `base` that belong to it
+fn partial_files(base: &Path) -> Vec<PathBuf> {
+    let (Some(dir), Some(base_name)) = (base.parent(), base.file_name()) else {
+        return Vec::new();
+    };
+    let base_name = base_name.to_string_lossy();
+    let Ok(entries) = fs::read_dir(dir) else {
+        return Vec::new();
+    };
+    entries
+        .flatten()
+        .filter(|e| is_partial_of(&e.file_name().to_string_lossy(), &base_name))
+        .map(|e| e.path())
+        .collect()
+}
+
+/// Files a cancelled task leaves behind. Sidecars are always included;
+/// partial downloads and unmerged formats only when `keep_partials` is off,
+/// since a new download of the same video resumes from them.
+pub fn task_artifacts(task: &DownloadTask, keep_partials: bool) -> Vec<PathBuf> {
+    let sidecar_prefix = format!(".{}.", task.id);
+    let (sidecars, destinations): (Vec<&PathBuf>, Vec<&PathBuf>) =
+        task.temp_files.iter().partition(|path| {
+            path.file_name()
+                .is_some_and(|name| name.to_string_lossy().starts_with(&sidecar_prefix))
+        });
+
+    let mut artifacts: Vec<PathBuf> = sidecars.into_iter().cloned().collect();
+    if !keep_partials {
+        for destination in destinations {
+            artifacts.extend(partial_files(destination));
+            // The finished output is kept, only intermediate formats go
+            if task.output_path.as_ref() != Some(destination) {
+                artifacts.push(destination.clone());
+            }
+        }
+        if let Some(output_path) = &task.output_path {
+            artifacts.extend(partial_files(output_path));
+        }
+    }
+    artifacts.sort();
+    artifacts.dedup();
+    artifacts.retain(|path| path.is_file());
+    artifacts
+}
+
+/// Remove files, returning the ones that were deleted
+pub fn remove_files(paths: Vec<PathBuf>) -> Vec<PathBuf> {
+    paths
+        .into_iter()
+        .filter(|path| fs::remove_file(path).is_ok())
+        .collect()
+}
+
+/// Whether a file was modified too recently to be left over, it may belong
+/// to a download that is just starting
+fn is_recent(path: &Path) -> bool {
+    fs::metadata(path)
+        .and_then(|metadata| metadata.modified())
+        .ok()
+        .and_then(|modified| SystemTime::now().duration_since(modified).ok())
+        .is_none_or(|age| age < RECENT)
+}
+
+/// Leftover sidecars in `download_dir` and partial files of `leftovers` that
+/// no task in `tasks` owns. Recently modified files are skipped.
+pub fn find_orphans(
+    download_dir: &Path,
+    tasks: &[DownloadTask],
+    leftovers: &[PathBuf],
+) -> Vec<PathBuf> {
+    let owned = |path: &Path, name: &str| {
+        if let Some(id) = sidecar_task_id(name) {
+            return tasks.iter().any(|t| t.id == id);
+        }
+        tasks.iter().any(|task| {
+            task.temp_files.iter().chain(&task.output_path).any(|base| {
+                base == path
+                    || (base.parent() == path.parent()
+                        && base
+                            .file_name()
+                            .is_some_and(|b| is_partial_of(name, &b.to_string_lossy())))
+            })
+        })
+    };
+
+    // Sidecars are only written at the top of the download directory
+    let sidecars = fs::read_dir(download_dir)
+        .into_iter()
+        .flatten()
+        .flatten()
+        .filter(|e| sidecar_task_id(&e.file_name().to_string_lossy()).is_some())
+        .map(|e| e.path());
+    let partials = leftovers.iter().flat_map(|base| partial_files(base));
+
+    let mut orphans: Vec<PathBuf> = sidecars
+        .chain(partials)
+        .filter(|path| {
+            let name = path
+                .file_name()
+                .map(|n| n.to_string_lossy().to_string())
+                .unwrap_or_default();
+            path.is_file() && !is_recent(path) && !owned(path, &name)
+        })
+        .collect();
+    orphans.sort();
+    orphans.dedup();
+    orphans
+}
+
+/// Destinations of removed tasks, whose partial files may still be on disk.
+/// Stored in `leftovers.json` so they can be cleaned up in a later session.
+pub struct LeftoverStore {
+    path: PathBuf,
+    /// Serializes rewrites of the leftovers file
+    write_lock: Mutex<()>,
+}
+
+impl LeftoverStore {
+    pub fn new(app_data_dir: PathBuf) -> Self {
+        Self {
+            path: app_data_dir.join("leftovers.json"),
+            write_lock: Mutex::new(()),
+        }
+    }
+
+    pub fn paths(&self) -> Vec<PathBuf> {
+        fs::read_to_string(&self.path)
+            .ok()
+            .and_then(|content| serde_json::from_str(&content).ok())
+            .unwrap_or_default()
+    }
+
+    /// Remember the files a task wrote before it is removed
+    pub fn record(&self, task: &DownloadTask) {
+        let _guard = self.write_lock.lock().unwrap();
+        let mut paths = self.paths();
+        for path in task.temp_files.iter().chain(&task.output_path) {
+            if !paths.contains(path) {
+                paths.push(path.clone());
+            }
+        }
+        self.write(&paths);
+    }
+
+    /// Forget destinations that have no partial files left
+    pub fn prune(&self) {
+        let _guard = self.write_lock.lock().unwrap();
+        let mut paths = self.paths();
+        paths.retain(|base| !partial_files(base).is_empty());
+        self.write(&paths);
+    }
+
+    fn write(&self, paths: &[PathBuf]) {
+        if paths.is_empty() {
+            let _ = fs::remove_file(&self.path);
+        } else if let Ok(json) = serde_json::to_string(paths) {
+            let _ = fs::write(&self.path, json);
+        }
+    }
+}
@@ -0,0 +1,131 @@
+//! Time range and chapter clips
+//!
+//! Clips are passed to yt-dlp's `--download-sections`, which cuts them with
+//! ffmpeg while downloading. Each section is written to its own file, with
+//! the range appended to the filename so clips of one video don't collide.
+
+use crate::models::{ClipOptions, VideoInfo};
+
+/// Parse `90`, `1:30` or `1:02:03.5` into seconds
+pub fn parse_timestamp(value: &str) -> Option<f64> {
+    let value = value.trim();
+    if value.is_empty() {
+        return None;
+    }
+
+    let mut seconds = 0.0;
+    for part in value.split(':') {
+        let part: f64 = part.parse().ok()?;
+        if part < 0.0 {
+            return None;
+        }
+        seconds = seconds * 60.0 + part;
+    }
+    Some(seconds)
+}
+
+/// Check that every range parses and ends after it starts
+pub fn validate_clip(clip: &ClipOptions) -> Result<(), String> {
+    if clip.sections.is_empty() && clip.chapters.is_empty() {
+        return Err("A clip needs at least one time range or chapter".to_string());
+    }
+
+    for section in &clip.sections {
+        let start = parse_timestamp(&section.start)
+            .ok_or_else(|| format!("Invalid start time: {}", section.start))?;
+        if let Some(end) = section.end.as_deref().filter(|e| !e.trim().is_empty()) {
+            let end = parse_timestamp(end).ok_or_else(|| format!("Invalid end time: {}", end))?;
+            if end <= start {
+                return Err(format!(
+                    "Clip end {} must be after its start {}",
+                    end, section.start
+                ));
+            }
+        }
+    }
+
+    if clip.chapters.iter().any(|name| name.trim().is_empty()) {
+        return Err("Chapter names cannot be empty".to_string());
+    }
+
+    Ok(())
+}
+
+/// yt-dlp arguments that select the sections to download
+pub fn clip_args(clip: &ClipOptions) -> Vec<String> {
+    let mut args = Vec::new();
+
+    for section in &clip.sections {
+        let start = parse_timestamp(&section.start).unwrap_or(0.0);
+        let end = section
+            .end
+            .as_deref()
+            .and_then(parse_timestamp)
+            .map(|e| e.to_string())
+            .unwrap_or_else(|| "inf".to_string());
+        args.push("--download-sections".to_string());
+        args.push(format!("*{}-{}", start, end));
+    }
+    for name in &clip.chapters {
+        // yt-dlp treats chapter selectors as regexes
+        args.push("--download-sections".to_string());
+        args.push(regex::escape(name.trim()));
+    }
+
+    if clip.precise_cuts {
+        args.push("--force-keyframes-at-cuts".to_string());
+    }
+
+    args
+}
+
+/// Insert the section range before the extension of a yt-dlp output template
+pub fn with_range_suffix(output_template: &str) -> String {
+    let suffix = " [%(section_start>%H.%M.%S)s-%(section_end>%H.%M.%S)s]";
+    match output_template.rfind(".%(ext)s") {
+        Some(pos) => format!(
+            "{}{}{}",
+            &output_template[..pos],
+            suffix,
+            &output_template[pos..]
+        ),
+        None => format!("{}{}", output_template, suffix),
+    }
+}
+
+/// Total length of the selected sections, in seconds, if it can be known
+pub fn clip_duration(clip: &ClipOptions, info: Option<&VideoInfo>) -> Option<f64> {
+    let video_duration = info.and_then(|i| i.duration).map(|d| d as f64);
+    let mut total = 0.0;
+
+    for section in &clip.sections {
+        let start = parse_timestamp(&section.start)?;
+        let end = match section.end.as_deref().and_then(parse_timestamp) {
+            Some(end) => end,
+            None => video_duration?,
+        };
+        total += (end.min(video_duration.unwrap_or(end)) - start).max(0.0);
+    }
+
+    if !clip.chapters.is_empty() {
+        let chapters = &info?.chapters;
+        if chapters.is_empty() {
+            return None;
+        }
+        total += chapters
+            .iter()
+            .filter(|c| {
+                clip.chapters
+                    .iter()
+                    .any(|name| c.title.contains(name.trim()))
+            })
+            .map(|c| c.end_time - c.start_time)
+            .sum::<f64>();
+    }
+
+    if total > 0.0 {
+        Some(total)
+    } else {
+        None
+    }
+}
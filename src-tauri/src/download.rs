@@ -1,15 +1,32 @@
-use crate::models::{DownloadProgressEvent, DownloadStatus, DownloadTask, VideoInfo};
+use crate::archive::ArchiveStore;
+use crate::cleanup::{self, LeftoverStore};
+use crate::clip;
+use crate::format;
+use crate::live;
+use crate::metadata;
+use crate::models::{
+    AppSettings, Chapter, DownloadProgressEvent, DownloadStatus, DownloadTask, FormatInfo,
+    PlaylistEntry, PlaylistFilter, ScheduleOptions, TaskOptions, VideoInfo,
+};
+use crate::playlist;
+use crate::process::{self, ProcessTable, ProcessTree};
+use crate::progress::{self, ProgressUpdate};
+use crate::queue::QueueStore;
+use crate::schedule;
+use crate::sponsorblock;
+use crate::subtitles;
+use crate::template;
 use crate::ytdlp::YtDlpManager;
 use regex::Regex;
-use std::collections::HashMap;
-use std::io::{BufRead, BufReader};
-use std::path::PathBuf;
+use std::cmp::Reverse;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::path::{Path, PathBuf};
 use std::process::{Command, Stdio};
 use std::sync::atomic::{AtomicU32, Ordering};
-use std::sync::{Arc, RwLock};
-use std::thread;
-use tauri::{AppHandle, Emitter};
-use tokio::sync::Notify;
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::Duration;
+use tauri::{AppHandle, Emitter, Runtime, Wry};
+use tokio::sync::{Notify, OwnedSemaphorePermit, Semaphore};
 use uuid::Uuid;
 
 #[cfg(target_os = "windows")]
@@ -21,49 +38,303 @@ const CREATE_NO_WINDOW: u32 = 0x08000000;
 use crate::aria2::Aria2Manager;
 use crate::ffmpeg::FFmpegManager;
 
-pub struct DownloadManager {
+/// Generic over the Tauri runtime so tests can drive it with a mock app
+pub struct DownloadManager<R: Runtime = Wry> {
     tasks: Arc<RwLock<HashMap<String, DownloadTask>>>,
+    /// On-disk copy of `tasks`, rewritten on every state transition
+    store: Arc<QueueStore>,
+    /// Videos that finished downloading, shared with yt-dlp's --download-archive
+    archive: Arc<ArchiveStore>,
+    /// Files written by removed tasks, for cleaning up their leftovers
+    leftovers: Arc<LeftoverStore>,
     /// Maps task_id to process ID for cancellation
-    process_ids: Arc<RwLock<HashMap<String, u32>>>,
+    processes: Arc<ProcessTable>,
+    /// Recordings the user asked to stop, which finish as Completed
+    stop_requests: Arc<RwLock<HashSet<String>>>,
+    /// Number of times each task has been started. A run whose process exits
+    /// after the task was started again leaves the task to the newer run.
+    runs: Arc<RwLock<HashMap<String, u64>>>,
     ytdlp: Arc<YtDlpManager>,
     ffmpeg: Arc<FFmpegManager>,
     aria2: Arc<Aria2Manager>,
     /// Current number of active downloads
     active_downloads: Arc<AtomicU32>,
+    /// Tasks started with start_now, which hold no slot. A task restarted
+    /// before its previous run exited is listed once per run.
+    unslotted_tasks: Arc<Mutex<Vec<String>>>,
     /// Maximum concurrent downloads (dynamically adjustable)
     max_concurrent: Arc<RwLock<u32>>,
-    /// Notifier for waking up waiting tasks
+    /// One permit per download slot, resized with max_concurrent
+    slots: Arc<Semaphore>,
+    /// Tasks waiting for a slot
+    queue: Arc<Mutex<VecDeque<QueuedDownload<R>>>>,
+    /// Total download speed limit in KiB/s, 0 = unlimited (dynamically adjustable)
+    rate_limit_kib: Arc<RwLock<u64>>,
+    /// Daily download window (dynamically adjustable)
+    schedule: Arc<RwLock<ScheduleOptions>>,
+    /// Wakes the scheduler when the queue or the schedule changes
     notify: Arc<Notify>,
 }
 
-impl DownloadManager {
+// Not derived, derive(Clone) would require the runtime itself to be Clone
+impl<R: Runtime> Clone for DownloadManager<R> {
+    fn clone(&self) -> Self {
+        Self {
+            tasks: self.tasks.clone(),
+            store: self.store.clone(),
+            archive: self.archive.clone(),
+            leftovers: self.leftovers.clone(),
+            processes: self.processes.clone(),
+            stop_requests: self.stop_requests.clone(),
+            runs: self.runs.clone(),
+            ytdlp: self.ytdlp.clone(),
+            ffmpeg: self.ffmpeg.clone(),
+            aria2: self.aria2.clone(),
+            active_downloads: self.active_downloads.clone(),
+            unslotted_tasks: self.unslotted_tasks.clone(),
+            max_concurrent: self.max_concurrent.clone(),
+            slots: self.slots.clone(),
+            queue: self.queue.clone(),
+            rate_limit_kib: self.rate_limit_kib.clone(),
+            schedule: self.schedule.clone(),
+            notify: self.notify.clone(),
+        }
+    }
+}
+
+/// A download waiting in the queue for a slot
+struct QueuedDownload<R: Runtime> {
+    task_id: String,
+    settings: AppSettings,
+    app_handle: AppHandle<R>,
+    cookies_path: PathBuf,
+}
+
+/// A running download's slot. Dropping it frees the slot on every exit path,
+/// including early returns and panics. Tasks started with start_now hold no permit.
+struct DownloadSlot {
+    _permit: Option<OwnedSemaphorePermit>,
+    active_downloads: Arc<AtomicU32>,
+    /// List of slotless tasks this one is in, for tasks started with start_now
+    unslotted: Option<(Arc<Mutex<Vec<String>>>, String)>,
+}
+
+impl DownloadSlot {
+    fn new(permit: OwnedSemaphorePermit, active_downloads: Arc<AtomicU32>) -> Self {
+        active_downloads.fetch_add(1, Ordering::SeqCst);
+        Self {
+            _permit: Some(permit),
+            active_downloads,
+            unslotted: None,
+        }
+    }
+
+    fn unslotted(
+        active_downloads: Arc<AtomicU32>,
+        unslotted_tasks: Arc<Mutex<Vec<String>>>,
+        task_id: String,
+    ) -> Self {
+        active_downloads.fetch_add(1, Ordering::SeqCst);
+        unslotted_tasks.lock().unwrap().push(task_id.clone());
+        Self {
+            _permit: None,
+            active_downloads,
+            unslotted: Some((unslotted_tasks, task_id)),
+        }
+    }
+}
+
+impl Drop for DownloadSlot {
+    fn drop(&mut self) {
+        self.active_downloads.fetch_sub(1, Ordering::SeqCst);
+        if let Some((unslotted_tasks, task_id)) = &self.unslotted {
+            let mut unslotted_tasks = unslotted_tasks.lock().unwrap();
+            if let Some(pos) = unslotted_tasks.iter().position(|id| id == task_id) {
+                unslotted_tasks.swap_remove(pos);
+            }
+        }
+    }
+}
+
+impl<R: Runtime> DownloadManager<R> {
     pub fn new(
+        app_data_dir: PathBuf,
         ytdlp: Arc<YtDlpManager>,
         ffmpeg: Arc<FFmpegManager>,
         aria2: Arc<Aria2Manager>,
         max_concurrent: u32,
+        rate_limit_kib: u64,
+        schedule: ScheduleOptions,
     ) -> Self {
+        let archive = ArchiveStore::new(app_data_dir.clone());
+        let processes = ProcessTable::new(app_data_dir.clone());
+        let store = QueueStore::new(app_data_dir.clone());
+        // Renumber positions, queues saved before ordering existed have none
+        let mut loaded = store.load();
+        loaded.sort_by_key(|t| t.queue_position);
+        let tasks: HashMap<String, DownloadTask> = loaded
+            .into_iter()
+            .enumerate()
+            .map(|(position, mut task)| {
+                task.queue_position = position as u64;
+                (task.id.clone(), task)
+            })
+            .collect();
+
+        let tasks = Arc::new(RwLock::new(tasks));
+        let store = Arc::new(store);
+        store.clone().spawn_writer(tasks.clone());
+
         Self {
-            tasks: Arc::new(RwLock::new(HashMap::new())),
-            process_ids: Arc::new(RwLock::new(HashMap::new())),
+            tasks,
+            store,
+            archive: Arc::new(archive),
+            leftovers: Arc::new(LeftoverStore::new(app_data_dir)),
+            processes: Arc::new(processes),
+            stop_requests: Arc::new(RwLock::new(HashSet::new())),
+            runs: Arc::new(RwLock::new(HashMap::new())),
             ytdlp,
             ffmpeg,
             aria2,
             active_downloads: Arc::new(AtomicU32::new(0)),
+            unslotted_tasks: Arc::new(Mutex::new(Vec::new())),
             max_concurrent: Arc::new(RwLock::new(max_concurrent)),
+            slots: Arc::new(Semaphore::new(max_concurrent as usize)),
+            queue: Arc::new(Mutex::new(VecDeque::new())),
+            rate_limit_kib: Arc::new(RwLock::new(rate_limit_kib)),
+            schedule: Arc::new(RwLock::new(schedule)),
             notify: Arc::new(Notify::new()),
         }
     }
 
-    /// Update max concurrent downloads - takes effect immediately
+    pub fn archive(&self) -> &ArchiveStore {
+        &self.archive
+    }
+
+    /// Update max concurrent downloads. Raising the limit starts queued tasks
+    /// immediately, lowering it takes effect as running downloads finish.
     pub fn set_max_concurrent(&self, max: u32) {
-        *self.max_concurrent.write().unwrap() = max;
-        // Wake up waiting tasks to check new limit
-        self.notify.notify_waiters();
+        let mut current = self.max_concurrent.write().unwrap();
+        if max > *current {
+            self.slots.add_permits((max - *current) as usize);
+        } else if max < *current {
+            // Retire the extra permits once running downloads hand them back
+            let slots = self.slots.clone();
+            let excess = *current - max;
+            tauri::async_runtime::spawn(async move {
+                if let Ok(permits) = slots.acquire_many_owned(excess).await {
+                    permits.forget();
+                }
+            });
+        }
+        *current = max;
+    }
+
+    /// Update the total download speed limit. Queued tasks get their share of
+    /// the new limit when they start; running downloads keep theirs until
+    /// restarted with `restart_downloads`.
+    pub fn set_rate_limit(&self, kib: u64) {
+        *self.rate_limit_kib.write().unwrap() = kib;
     }
 
-    pub fn create_task(&self, url: String, resolution: String) -> DownloadTask {
-        let task = DownloadTask {
+    /// Restart running downloads so they pick up their share of a new speed
+    /// limit. yt-dlp continues from the .part files. Tasks started with
+    /// start_now are restarted the same way, so they keep bypassing the slots.
+    pub fn restart_downloads(
+        &self,
+        settings: &AppSettings,
+        app_handle: &AppHandle<R>,
+        cookies_path: &Path,
+    ) {
+        for task in self.get_all_tasks() {
+            if matches!(
+                task.status,
+                DownloadStatus::Fetching | DownloadStatus::Downloading
+            ) {
+                let started_now = self.unslotted_tasks.lock().unwrap().contains(&task.id);
+                self.pause_download(&task.id);
+                let restart = if started_now {
+                    Self::start_now
+                } else {
+                    Self::start_download
+                };
+                restart(
+                    self,
+                    task.id,
+                    settings.clone(),
+                    app_handle.clone(),
+                    cookies_path.to_path_buf(),
+                );
+            }
+        }
+    }
+
+    /// Update the download window - the scheduler re-checks it immediately
+    pub fn set_schedule(&self, schedule: ScheduleOptions) {
+        *self.schedule.write().unwrap() = schedule;
+        self.notify.notify_one();
+    }
+
+    /// Speed limit for a task starting now: the total limit split into one
+    /// share per slot plus one per download started without a slot, capped by
+    /// the task's own limit. Running downloads together stay within the total,
+    /// but a share is not raised while other slots are idle.
+    fn task_rate_limit(total_kib: u64, shares: u32, task_kib: Option<u64>) -> Option<u64> {
+        let share = (total_kib > 0).then(|| (total_kib / shares.max(1) as u64).max(1));
+        match (share, task_kib.filter(|&kib| kib > 0)) {
+            (Some(share), Some(task)) => Some(share.min(task)),
+            (share, task) => share.or(task),
+        }
+    }
+
+    /// How many times a task has been started, identifying its latest run
+    fn current_run(&self, task_id: &str) -> u64 {
+        self.runs.read().unwrap().get(task_id).copied().unwrap_or(0)
+    }
+
+    /// Save the current queue to disk once changes settle
+    fn persist(&self) {
+        self.store.request_save();
+    }
+
+    /// Write queue changes that haven't been saved yet, before the app exits
+    pub fn flush_queue(&self) {
+        self.store.flush(&self.tasks.read().unwrap());
+    }
+
+    pub fn create_task(
+        &self,
+        url: String,
+        resolution: String,
+        options: TaskOptions,
+    ) -> Result<DownloadTask, String> {
+        if let Some(audio) = &options.audio {
+            format::validate_audio(audio)?;
+        }
+        if let Some(subs) = &options.subtitles {
+            subtitles::validate_subtitles(subs)?;
+        }
+        if let Some(output_template) = &options.output_template {
+            template::validate_template(output_template)?;
+        }
+        if let Some(clip) = &options.clip {
+            clip::validate_clip(clip)?;
+        }
+        if let Some(sponsorblock) = &options.sponsorblock {
+            sponsorblock::validate_sponsorblock(sponsorblock)?;
+        }
+        if let Some(metadata) = &options.metadata {
+            metadata::validate_metadata(metadata)?;
+        }
+        if Self::uses_archive(&options) {
+            if let Some(key) = ArchiveStore::key_for_url(&url) {
+                if self.archive.contains(&key) {
+                    return Err("This video is already in the download archive".to_string());
+                }
+            }
+        }
+
+        let mut task = DownloadTask {
             id: Uuid::new_v4().to_string(),
             url,
             video_info: None,
@@ -73,28 +344,176 @@ impl DownloadManager {
             eta: None,
             error: None,
             resolution,
+            options,
+            downloaded_bytes: None,
+            total_bytes: None,
+            stage: None,
             output_path: None,
+            retry_count: 0,
+            elapsed_secs: None,
+            chapter_files: Vec::new(),
+            removed_segments: Vec::new(),
+            time_saved_secs: None,
+            priority: 0,
+            queue_position: 0,
+            temp_files: Vec::new(),
+            paused_by_schedule: false,
         };
 
-        self.tasks
-            .write()
-            .unwrap()
-            .insert(task.id.clone(), task.clone());
-        task
+        // New tasks join the bottom of the queue
+        {
+            let mut tasks = self.tasks.write().unwrap();
+            task.queue_position = tasks
+                .values()
+                .map(|t| t.queue_position + 1)
+                .max()
+                .unwrap_or(0);
+            tasks.insert(task.id.clone(), task.clone());
+        }
+        self.persist();
+        Ok(task)
+    }
+
+    /// Create a task for a playlist entry, keeping its title and playlist position
+    pub fn create_task_from_entry(
+        &self,
+        entry: &PlaylistEntry,
+        resolution: String,
+        options: TaskOptions,
+    ) -> Result<DownloadTask, String> {
+        let mut task = self.create_task(entry.url.clone(), resolution, options)?;
+        let info = playlist::entry_video_info(entry);
+        if let Some(t) = self.tasks.write().unwrap().get_mut(&task.id) {
+            t.video_info = Some(info.clone());
+        }
+        self.persist();
+        task.video_info = Some(info);
+        Ok(task)
+    }
+
+    /// Change the options of a task that is not currently running
+    pub fn set_task_options(&self, task_id: &str, options: TaskOptions) -> Result<(), String> {
+        {
+            let mut tasks = self.tasks.write().unwrap();
+            let task = tasks
+                .get_mut(task_id)
+                .ok_or_else(|| "Task not found".to_string())?;
+
+            if task.status.is_active() {
+                return Err("Cannot change options while the task is running".to_string());
+            }
+
+            if let Some(audio) = &options.audio {
+                format::validate_audio(audio)?;
+            }
+            if let Some(subs) = &options.subtitles {
+                subtitles::validate_subtitles(subs)?;
+            }
+            if let Some(output_template) = &options.output_template {
+                template::validate_template(output_template)?;
+            }
+            if let Some(clip) = &options.clip {
+                clip::validate_clip(clip)?;
+            }
+            if let Some(sponsorblock) = &options.sponsorblock {
+                sponsorblock::validate_sponsorblock(sponsorblock)?;
+            }
+            if let Some(metadata) = &options.metadata {
+                metadata::validate_metadata(metadata)?;
+            }
+
+            let previous = std::mem::replace(&mut task.options, options);
+            if let Err(e) = format::validate_format_ids(task) {
+                task.options = previous;
+                return Err(e);
+            }
+        }
+        self.persist();
+        Ok(())
     }
 
     pub fn get_task(&self, task_id: &str) -> Option<DownloadTask> {
         self.tasks.read().unwrap().get(task_id).cloned()
     }
 
+    /// All tasks in queue order
     pub fn get_all_tasks(&self) -> Vec<DownloadTask> {
-        self.tasks.read().unwrap().values().cloned().collect()
+        let mut tasks: Vec<DownloadTask> = self.tasks.read().unwrap().values().cloned().collect();
+        tasks.sort_by_key(|t| t.queue_position);
+        tasks
+    }
+
+    /// Move a task to `index` in the queue and renumber the others
+    fn move_task(
+        &self,
+        task_id: &str,
+        index: impl FnOnce(&[String]) -> usize,
+    ) -> Result<(), String> {
+        {
+            let mut tasks = self.tasks.write().unwrap();
+            if !tasks.contains_key(task_id) {
+                return Err("Task not found".to_string());
+            }
+
+            let mut order: Vec<&DownloadTask> = tasks.values().collect();
+            order.sort_by_key(|t| t.queue_position);
+            let mut ids: Vec<String> = order
+                .into_iter()
+                .map(|t| t.id.clone())
+                .filter(|id| id != task_id)
+                .collect();
+            let index = index(&ids).min(ids.len());
+            ids.insert(index, task_id.to_string());
+
+            for (position, id) in ids.iter().enumerate() {
+                if let Some(t) = tasks.get_mut(id) {
+                    t.queue_position = position as u64;
+                }
+            }
+        }
+        self.persist();
+        self.notify.notify_one();
+        Ok(())
+    }
+
+    pub fn move_task_to_top(&self, task_id: &str) -> Result<(), String> {
+        self.move_task(task_id, |_| 0)
+    }
+
+    pub fn move_task_to_bottom(&self, task_id: &str) -> Result<(), String> {
+        self.move_task(task_id, |ids| ids.len())
+    }
+
+    /// Move a task directly in front of another one
+    pub fn move_task_before(&self, task_id: &str, before_id: &str) -> Result<(), String> {
+        if task_id == before_id {
+            return Ok(());
+        }
+        if self.get_task(before_id).is_none() {
+            return Err("Task not found".to_string());
+        }
+        self.move_task(task_id, |ids| {
+            ids.iter()
+                .position(|id| id == before_id)
+                .unwrap_or(ids.len())
+        })
+    }
+
+    pub fn set_task_priority(&self, task_id: &str, priority: i32) -> Result<(), String> {
+        match self.tasks.write().unwrap().get_mut(task_id) {
+            Some(task) => task.priority = priority,
+            None => return Err("Task not found".to_string()),
+        }
+        self.persist();
+        self.notify.notify_one();
+        Ok(())
     }
 
     pub fn update_task_status(&self, task_id: &str, status: DownloadStatus) {
         if let Some(task) = self.tasks.write().unwrap().get_mut(task_id) {
             task.status = status;
         }
+        self.persist();
     }
 
     pub fn update_task_error(&self, task_id: &str, error: String) {
@@ -102,76 +521,335 @@ impl DownloadManager {
             task.status = DownloadStatus::Failed;
             task.error = Some(error);
         }
+        self.persist();
     }
 
     pub fn update_task_video_info(&self, task_id: &str, info: VideoInfo) {
         if let Some(task) = self.tasks.write().unwrap().get_mut(task_id) {
             task.video_info = Some(info);
         }
+        self.persist();
     }
 
-    /// Cancel a running download by killing the yt-dlp process
-    pub fn cancel_download(&self, task_id: &str) {
-        // Kill the process if it exists
-        if let Some(pid) = self.process_ids.write().unwrap().remove(task_id) {
-            #[cfg(target_os = "windows")]
-            {
-                // Use taskkill on Windows to kill the process tree
-                let _ = Command::new("taskkill")
-                    .args(&["/F", "/T", "/PID", &pid.to_string()])
-                    .creation_flags(CREATE_NO_WINDOW)
-                    .output();
+    /// Cancel a running download by killing the yt-dlp process tree, then
+    /// remove its temporary files (keeping partial downloads if `keep_partials`)
+    pub fn cancel_download(&self, task_id: &str, keep_partials: bool) {
+        // Update task status first so the exiting process isn't reported as failed
+        let task = self.tasks.write().unwrap().get_mut(task_id).map(|task| {
+            let previous = task.clone();
+            task.status = DownloadStatus::Cancelled;
+            task.paused_by_schedule = false;
+            previous
+        });
+        self.persist();
+        self.processes.kill(task_id);
+
+        let Some(task) = task.filter(|t| t.status != DownloadStatus::Completed) else {
+            return;
+        };
+        let processes = self.processes.clone();
+        let tasks = self.tasks.clone();
+        let store = self.store.clone();
+        tauri::async_runtime::spawn(async move {
+            // Open files can't be removed on Windows, wait for the tree to exit
+            while processes.pid(&task.id).is_some() {
+                tokio::time::sleep(Duration::from_millis(100)).await;
             }
-            #[cfg(not(target_os = "windows"))]
-            {
-                // Use kill on Unix
-                let _ = Command::new("kill")
-                    .args(&["-9", &pid.to_string()])
-                    .output();
+            // Leave the files alone if the task was restarted meanwhile
+            let status = tasks
+                .read()
+                .unwrap()
+                .get(&task.id)
+                .map(|t| t.status.clone());
+            if status.is_some_and(|s| s != DownloadStatus::Cancelled) {
+                return;
             }
-        }
-        // Update task status
+
+            let task = tasks.read().unwrap().get(&task.id).cloned().unwrap_or(task);
+            cleanup::remove_files(cleanup::task_artifacts(&task, keep_partials));
+            if let Some(t) = tasks.write().unwrap().get_mut(&task.id) {
+                t.temp_files.clear();
+            }
+            store.request_save();
+        });
+    }
+
+    /// Pause a running download by interrupting the yt-dlp process tree.
+    /// The .part file is preserved so download can be resumed; a tree that
+    /// hasn't exited after `STOP_TIMEOUT` is killed.
+    pub fn pause_download(&self, task_id: &str) {
+        self.pause(task_id, false);
+    }
+
+    /// Pause a running download because the download window closed. The mark
+    /// is saved with the task so it is resumed even after a restart.
+    pub fn pause_for_schedule(&self, task_id: &str) {
+        self.pause(task_id, true);
+    }
+
+    fn pause(&self, task_id: &str, by_schedule: bool) {
+        // Update task status to Paused (not Cancelled)
         if let Some(task) = self.tasks.write().unwrap().get_mut(task_id) {
-            task.status = DownloadStatus::Cancelled;
+            task.status = DownloadStatus::Paused;
+            task.paused_by_schedule = by_schedule;
         }
+        self.persist();
+
+        let Some(pid) = self.processes.pid(task_id) else {
+            return;
+        };
+        self.processes.interrupt(task_id);
+
+        let processes = self.processes.clone();
+        let task_id = task_id.to_string();
+        tauri::async_runtime::spawn(async move {
+            tokio::time::sleep(process::STOP_TIMEOUT).await;
+            processes.kill_if_running(&task_id, pid);
+        });
     }
 
-    /// Pause a running download by killing the yt-dlp process
-    /// The .part file is preserved so download can be resumed
-    pub fn pause_download(&self, task_id: &str) {
-        // Kill the process if it exists
-        if let Some(pid) = self.process_ids.write().unwrap().remove(task_id) {
-            #[cfg(target_os = "windows")]
-            {
-                let _ = Command::new("taskkill")
-                    .args(&["/F", "/T", "/PID", &pid.to_string()])
-                    .creation_flags(CREATE_NO_WINDOW)
-                    .output();
+    /// Stop a live recording gracefully so the recorded file is finalized
+    pub fn stop_recording(&self, task_id: &str) -> Result<(), String> {
+        let status = self.get_task(task_id).map(|t| t.status);
+        if status != Some(DownloadStatus::Recording) {
+            return Err("Task is not recording".to_string());
+        }
+
+        if self.processes.pid(task_id).is_none() {
+            return Err("Recording process not found".to_string());
+        }
+        self.stop_requests
+            .write()
+            .unwrap()
+            .insert(task_id.to_string());
+        self.processes.interrupt(task_id);
+        Ok(())
+    }
+
+    /// Apply a progress update from a live recording: elapsed time and bytes
+    /// instead of a percentage
+    fn record_progress(
+        tasks: &RwLock<HashMap<String, DownloadTask>>,
+        store: &QueueStore,
+        app_handle: &AppHandle<R>,
+        task_id: &str,
+        update: &ProgressUpdate,
+    ) {
+        let speed = update.speed.map(progress::format_speed);
+        let elapsed_secs = update.elapsed.map(|e| e as u64);
+
+        let started = {
+            let mut tasks = tasks.write().unwrap();
+            let Some(t) = tasks.get_mut(task_id) else {
+                return;
+            };
+            let started = t.status != DownloadStatus::Recording;
+            t.status = DownloadStatus::Recording;
+            t.stage = None;
+            t.speed = speed.clone();
+            t.eta = None;
+            if update.downloaded_bytes.is_some() {
+                t.downloaded_bytes = update.downloaded_bytes;
             }
-            #[cfg(not(target_os = "windows"))]
-            {
-                let _ = Command::new("kill")
-                    .args(&["-9", &pid.to_string()])
-                    .output();
+            if elapsed_secs.is_some() {
+                t.elapsed_secs = elapsed_secs;
+            }
+            started
+        };
+
+        if started {
+            store.request_save();
+            let _ = app_handle.emit(
+                "download-status-changed",
+                DownloadProgressEvent::new(task_id, 0.0, DownloadStatus::Recording),
+            );
+        }
+
+        let _ = app_handle.emit(
+            "download-progress",
+            DownloadProgressEvent {
+                speed,
+                downloaded_bytes: update.downloaded_bytes,
+                speed_bytes: update.speed,
+                elapsed_secs,
+                ..DownloadProgressEvent::new(task_id, 0.0, DownloadStatus::Recording)
+            },
+        );
+    }
+
+    /// Apply ffmpeg progress while cutting clips, measured against the clipped duration
+    fn clip_progress(
+        tasks: &RwLock<HashMap<String, DownloadTask>>,
+        store: &QueueStore,
+        app_handle: &AppHandle<R>,
+        task_id: &str,
+        percent: Option<f64>,
+        update: &ProgressUpdate,
+    ) {
+        let speed = update.speed.map(progress::format_speed);
+
+        let (started, progress) = {
+            let mut tasks = tasks.write().unwrap();
+            let Some(t) = tasks.get_mut(task_id) else {
+                return;
+            };
+            let started = t.status != DownloadStatus::Downloading;
+            t.status = DownloadStatus::Downloading;
+            t.stage = None;
+            t.speed = speed.clone();
+            t.downloaded_bytes = update.downloaded_bytes;
+            if let Some(percent) = percent {
+                t.progress = percent;
             }
+            (started, t.progress)
+        };
+
+        if started {
+            store.request_save();
+            let _ = app_handle.emit(
+                "download-status-changed",
+                DownloadProgressEvent::new(task_id, progress, DownloadStatus::Downloading),
+            );
         }
-        // Update task status to Paused (not Cancelled)
-        if let Some(task) = self.tasks.write().unwrap().get_mut(task_id) {
-            task.status = DownloadStatus::Paused;
+
+        let _ = app_handle.emit(
+            "download-progress",
+            DownloadProgressEvent {
+                speed,
+                downloaded_bytes: update.downloaded_bytes,
+                speed_bytes: update.speed,
+                ..DownloadProgressEvent::new(task_id, progress, DownloadStatus::Downloading)
+            },
+        );
+    }
+
+    /// yt-dlp arguments that split the finished file into one file per
+    /// chapter, in a folder named after the output file. Splitting runs
+    /// after SponsorBlock has cut segments. The streams are copied, so each
+    /// file starts at the keyframe nearest its chapter; `precise` forces
+    /// keyframes at the chapter starts instead, which re-encodes the video.
+    fn split_chapter_args(output_template: &str, precise: bool) -> Vec<String> {
+        let folder = output_template
+            .strip_suffix(".%(ext)s")
+            .map(str::to_string)
+            .unwrap_or_else(|| format!("{} chapters", output_template));
+        let chapter_template =
+            Path::new(&folder).join("%(section_number)02d - %(section_title)s.%(ext)s");
+        let mut args = vec![
+            "--split-chapters".to_string(),
+            "--output".to_string(),
+            format!("chapter:{}", chapter_template.to_string_lossy()),
+        ];
+        if precise {
+            args.push("--force-keyframes-at-cuts".to_string());
+        }
+        args
+    }
+
+    /// Chapter file announced by yt-dlp's SplitChapters postprocessor, e.g.
+    /// `[SplitChapters] Chapter 001; Destination: Video/01 - Intro.mp4`
+    fn chapter_destination(line: &str) -> Option<PathBuf> {
+        let rest = line.strip_prefix("[SplitChapters] Chapter ")?;
+        let (_, path) = rest.split_once("; Destination: ")?;
+        Some(PathBuf::from(path.trim()))
+    }
+
+    /// Reset a failed or cancelled task so it can be started again
+    pub fn retry_download(&self, task_id: &str) -> Result<(), String> {
+        {
+            let mut tasks = self.tasks.write().unwrap();
+            let task = tasks
+                .get_mut(task_id)
+                .ok_or_else(|| "Task not found".to_string())?;
+
+            if task.status != DownloadStatus::Failed && task.status != DownloadStatus::Cancelled {
+                return Err("Only failed or cancelled tasks can be retried".to_string());
+            }
+
+            task.status = DownloadStatus::Pending;
+            task.progress = 0.0;
+            task.speed = None;
+            task.eta = None;
+            task.error = None;
+            task.downloaded_bytes = None;
+            task.retry_count = 0;
+            task.chapter_files.clear();
+            task.removed_segments.clear();
+            task.time_saved_secs = None;
         }
+        self.persist();
+        Ok(())
     }
 
-    pub fn remove_task(&self, task_id: &str) {
+    pub fn remove_task(&self, task_id: &str, keep_partials: bool) {
         // First cancel any running download
-        self.cancel_download(task_id);
+        self.cancel_download(task_id, keep_partials);
         // Then remove from tasks
-        self.tasks.write().unwrap().remove(task_id);
+        let removed = self.tasks.write().unwrap().remove(task_id);
+        if let Some(task) = removed {
+            self.leftovers.record(&task);
+        }
+        self.persist();
     }
 
     pub fn clear_completed(&self) {
+        let mut removed = Vec::new();
         self.tasks.write().unwrap().retain(|_, task| {
-            task.status != DownloadStatus::Completed && task.status != DownloadStatus::Failed
+            let keep =
+                task.status != DownloadStatus::Completed && task.status != DownloadStatus::Failed;
+            if !keep {
+                removed.push(task.clone());
+            }
+            keep
         });
+        for task in &removed {
+            self.leftovers.record(task);
+        }
+        self.persist();
+    }
+
+    /// Leftover temporary files of this app's downloads that no task owns
+    pub fn find_orphans(&self, download_dir: &Path) -> Vec<PathBuf> {
+        let tasks: Vec<DownloadTask> = self.tasks.read().unwrap().values().cloned().collect();
+        cleanup::find_orphans(download_dir, &tasks, &self.leftovers.paths())
+    }
+
+    /// Delete the given files, skipping any that are no longer leftovers.
+    /// Returns the deleted paths.
+    pub fn delete_orphans(&self, download_dir: &Path, paths: Vec<PathBuf>) -> Vec<PathBuf> {
+        let orphans = self.find_orphans(download_dir);
+        let deleted =
+            cleanup::remove_files(paths.into_iter().filter(|p| orphans.contains(p)).collect());
+        self.leftovers.prune();
+        deleted
+    }
+
+    /// Record files a download writes so they can be removed if it is cancelled
+    fn track_temp_files(
+        tasks: &RwLock<HashMap<String, DownloadTask>>,
+        store: &QueueStore,
+        task_id: &str,
+        paths: impl IntoIterator<Item = PathBuf>,
+    ) {
+        let mut changed = false;
+        if let Some(t) = tasks.write().unwrap().get_mut(task_id) {
+            for path in paths {
+                if !t.temp_files.contains(&path) {
+                    t.temp_files.push(path);
+                    changed = true;
+                }
+            }
+        }
+        if changed {
+            store.request_save();
+        }
+    }
+
+    /// Whether a task is checked against and recorded in the download archive.
+    /// Clips and live recordings aren't: a clip is not the whole video, and
+    /// several clips of one video must not block each other.
+    fn uses_archive(options: &TaskOptions) -> bool {
+        !options.ignore_archive && options.clip.is_none() && options.live.is_none()
     }
 
     /// Check if URL should use --no-playlist flag
@@ -181,12 +859,16 @@ impl DownloadManager {
         url.contains("v=") || url.contains("youtu.be/")
     }
 
-    /// Expand a playlist URL into individual video URLs
+    /// Expand a playlist URL into its entries, skipping archived videos and
+    /// anything the filter rejects
     pub fn expand_playlist(
         &self,
         url: &str,
         cookies_path: &PathBuf,
-    ) -> Result<Vec<String>, String> {
+        filter: &PlaylistFilter,
+    ) -> Result<Vec<PlaylistEntry>, String> {
+        let title_regex = playlist::validate_filter(filter)?;
+
         let exe_path = self.ytdlp.get_exe_path();
         if !exe_path.exists() {
             return Err("yt-dlp not installed".to_string());
@@ -195,6 +877,10 @@ impl DownloadManager {
         let mut cmd = Command::new(&exe_path);
         cmd.args(&["--flat-playlist", "--dump-json", "--no-warnings"]);
 
+        if let Some(items) = &filter.items {
+            cmd.args(["--playlist-items", items.replace(' ', "").as_str()]);
+        }
+
         if cookies_path.exists() {
             cmd.args(&["--cookies", &cookies_path.to_string_lossy()]);
         }
@@ -214,444 +900,1313 @@ impl DownloadManager {
         }
 
         let stdout = String::from_utf8_lossy(&output.stdout);
-        let mut video_urls = Vec::new();
+        let mut entries = Vec::new();
+        let archived: HashSet<String> = self.archive.entries().into_iter().collect();
 
         // Each line is a JSON object for one video in the playlist
-        for line in stdout.lines() {
-            if let Ok(json) = serde_json::from_str::<serde_json::Value>(line) {
-                if let Some(video_id) = json["id"].as_str() {
-                    video_urls.push(format!("https://www.youtube.com/watch?v={}", video_id));
-                } else if let Some(video_url) = json["url"].as_str() {
-                    video_urls.push(video_url.to_string());
+        for (position, line) in stdout.lines().enumerate() {
+            let Ok(json) = serde_json::from_str::<serde_json::Value>(line) else {
+                continue;
+            };
+            let Some(entry) = playlist::parse_entry(&json, position as u32 + 1) else {
+                continue;
+            };
+
+            // Skip videos that were already downloaded
+            if let Some(extractor) = &entry.extractor {
+                if archived.contains(&ArchiveStore::key(extractor, &entry.id)) {
+                    continue;
                 }
             }
+
+            if playlist::matches(&entry, filter, title_regex.as_ref()) {
+                entries.push(entry);
+            }
+        }
+
+        if filter.reverse {
+            entries.reverse();
+        }
+
+        Ok(entries)
+    }
+
+    /// Fetch full metadata for a single video with `yt-dlp -J` before downloading
+    pub fn probe_url(&self, url: &str, cookies_path: &Path) -> Result<VideoInfo, String> {
+        let exe_path = self.ytdlp.get_exe_path();
+        if !exe_path.exists() {
+            return Err("yt-dlp not installed".to_string());
+        }
+
+        let mut cmd = Command::new(&exe_path);
+        cmd.args(["-J", "--no-warnings"]);
+
+        if Self::should_use_no_playlist(url) {
+            cmd.arg("--no-playlist");
+        }
+
+        if cookies_path.exists() {
+            cmd.args(["--cookies", &cookies_path.to_string_lossy()]);
+        }
+
+        cmd.arg(url);
+        cmd.stdout(Stdio::piped()).stderr(Stdio::piped());
+
+        #[cfg(target_os = "windows")]
+        cmd.creation_flags(CREATE_NO_WINDOW);
+
+        let output = cmd
+            .output()
+            .map_err(|e| format!("Failed to run yt-dlp: {}", e))?;
+
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            return Err(stderr
+                .lines()
+                .rev()
+                .find_map(|line| line.strip_prefix("ERROR:"))
+                .map(|message| message.trim().to_string())
+                .unwrap_or_else(|| "Failed to fetch video info".to_string()));
+        }
+
+        let json: serde_json::Value = serde_json::from_slice(&output.stdout)
+            .map_err(|e| format!("Failed to parse video info: {}", e))?;
+
+        if json["_type"].as_str() == Some("playlist") {
+            return Err("URL is a playlist, expand it first".to_string());
+        }
+
+        Ok(Self::parse_video_info(&json))
+    }
+
+    /// Build VideoInfo from a yt-dlp info dict (`-J` output or `.info.json`)
+    pub fn parse_video_info(json: &serde_json::Value) -> VideoInfo {
+        let string = |key: &str| json[key].as_str().map(|s| s.to_string());
+
+        let formats = json["formats"]
+            .as_array()
+            .map(|formats| formats.iter().map(Self::parse_format).collect())
+            .unwrap_or_default();
+
+        VideoInfo {
+            id: json["id"].as_str().unwrap_or("unknown").to_string(),
+            url: string("webpage_url").unwrap_or_default(),
+            title: json["title"]
+                .as_str()
+                .unwrap_or("Unknown Title")
+                .to_string(),
+            duration: json["duration"].as_f64().map(|d| d as u64),
+            duration_string: string("duration_string"),
+            thumbnail: string("thumbnail"),
+            uploader: string("uploader"),
+            view_count: json["view_count"].as_u64(),
+            formats,
+            playlist_index: json["playlist_index"].as_u64().map(|i| i as u32),
+            playlist_count: json["playlist_count"].as_u64().map(|c| c as u32),
+            subtitles: subtitles::parse_tracks(json),
+            chapters: json["chapters"]
+                .as_array()
+                .map(|chapters| {
+                    chapters
+                        .iter()
+                        .map(|c| Chapter {
+                            title: c["title"].as_str().unwrap_or_default().to_string(),
+                            start_time: c["start_time"].as_f64().unwrap_or(0.0),
+                            end_time: c["end_time"].as_f64().unwrap_or(0.0),
+                        })
+                        .collect()
+                })
+                .unwrap_or_default(),
         }
+    }
+
+    fn parse_format(json: &serde_json::Value) -> FormatInfo {
+        let string = |key: &str| json[key].as_str().map(|s| s.to_string());
+        // yt-dlp uses "none" for a missing video or audio stream
+        let codec = |key: &str| string(key).filter(|c| c != "none");
+
+        FormatInfo {
+            format_id: string("format_id").unwrap_or_default(),
+            format_note: string("format_note"),
+            ext: string("ext").unwrap_or_default(),
+            resolution: string("resolution"),
+            filesize: json["filesize"].as_f64().map(|s| s as u64),
+            filesize_approx: json["filesize_approx"].as_f64().map(|s| s as u64),
+            vcodec: codec("vcodec"),
+            acodec: codec("acodec"),
+            width: json["width"].as_u64().map(|w| w as u32),
+            height: json["height"].as_u64().map(|h| h as u32),
+            fps: json["fps"].as_f64(),
+            tbr: json["tbr"].as_f64(),
+            dynamic_range: string("dynamic_range"),
+            audio_channels: json["audio_channels"].as_u64().map(|c| c as u32),
+            language: string("language"),
+            protocol: string("protocol"),
+        }
+    }
 
-        Ok(video_urls)
+    /// Check if a yt-dlp error message looks like a temporary network problem
+    /// that is worth retrying automatically
+    pub fn is_transient_error(error: &str) -> bool {
+        let error = error.to_lowercase();
+        [
+            "timed out",
+            "timeout",
+            "connection reset",
+            "connection aborted",
+            "connection refused",
+            "remote end closed",
+            "temporary failure",
+            "getaddrinfo failed",
+            "incompleteread",
+            "http error 429",
+            "http error 500",
+            "http error 502",
+            "http error 503",
+            "http error 504",
+            "unable to download video data",
+            "giving up after",
+        ]
+        .iter()
+        .any(|pattern| error.contains(pattern))
     }
 
-    /// Start download using saved cookies file
+    /// Queue a download using saved cookies file.
+    /// The scheduler starts it once a slot is free.
     pub fn start_download(
         &self,
         task_id: String,
-        download_dir: PathBuf,
-        app_handle: AppHandle,
+        settings: AppSettings,
+        app_handle: AppHandle<R>,
         cookies_path: PathBuf,
     ) {
-        let exe_path = self.ytdlp.get_exe_path();
-        let task = match self.get_task(&task_id) {
+        if !self.prepare_start(&task_id, &settings, &app_handle) {
+            return;
+        }
+
+        self.queue.lock().unwrap().push_back(QueuedDownload {
+            task_id,
+            settings,
+            app_handle,
+            cookies_path,
+        });
+        self.notify.notify_one();
+    }
+
+    /// Put tasks that were waiting when the app exited back on the queue,
+    /// in queue order
+    pub fn requeue_pending(
+        &self,
+        settings: &AppSettings,
+        app_handle: &AppHandle<R>,
+        cookies_path: &Path,
+    ) {
+        for task in self.get_all_tasks() {
+            if task.status == DownloadStatus::Pending {
+                self.start_download(
+                    task.id,
+                    settings.clone(),
+                    app_handle.clone(),
+                    cookies_path.to_path_buf(),
+                );
+            }
+        }
+    }
+
+    /// Start a download immediately, bypassing the concurrency limit, the
+    /// schedule and the task's start time
+    pub fn start_now(
+        &self,
+        task_id: String,
+        settings: AppSettings,
+        app_handle: AppHandle<R>,
+        cookies_path: PathBuf,
+    ) {
+        // Jump the queue if the task was waiting in it
+        self.queue.lock().unwrap().retain(|q| q.task_id != task_id);
+        if !self.prepare_start(&task_id, &settings, &app_handle) {
+            return;
+        }
+
+        let queued = QueuedDownload {
+            task_id,
+            settings,
+            app_handle,
+            cookies_path,
+        };
+        let slot = DownloadSlot::unslotted(
+            self.active_downloads.clone(),
+            self.unslotted_tasks.clone(),
+            queued.task_id.clone(),
+        );
+        let manager = self.clone();
+        tauri::async_runtime::spawn(async move {
+            manager.run_download(queued, slot).await;
+        });
+    }
+
+    /// Check that a task can start and mark it Pending.
+    /// Returns false if it is already queued or running, or is missing a tool.
+    fn prepare_start(
+        &self,
+        task_id: &str,
+        settings: &AppSettings,
+        app_handle: &AppHandle<R>,
+    ) -> bool {
+        let task = match self.get_task(task_id) {
             Some(t) => t,
-            None => return,
+            None => return false,
         };
 
+        // Already queued or running
+        if task.status.is_active()
+            || self
+                .queue
+                .lock()
+                .unwrap()
+                .iter()
+                .any(|q| q.task_id == task_id)
+        {
+            return false;
+        }
+
+        // Audio extraction, clipping and SponsorBlock need the bundled ffmpeg
+        let sponsorblock_options = sponsorblock::sponsorblock_options(&task, settings);
+        let needs_ffmpeg = if format::audio_options(&task, settings).is_some() {
+            Some("Audio extraction requires FFmpeg")
+        } else if task.options.clip.is_some() {
+            Some("Clip downloads require FFmpeg")
+        } else if sponsorblock::is_enabled(&sponsorblock_options) {
+            Some("SponsorBlock requires FFmpeg")
+        } else if task.options.split_chapters {
+            Some("Chapter splitting requires FFmpeg")
+        } else {
+            None
+        };
+        if let Some(message) = needs_ffmpeg {
+            if !self.ffmpeg.get_exe_path().exists() {
+                self.update_task_error(task_id, message.to_string());
+                let _ = app_handle.emit(
+                    "download-progress",
+                    DownloadProgressEvent::new(task_id, 0.0, DownloadStatus::Failed),
+                );
+                return false;
+            }
+        }
+
+        if let Some(t) = self.tasks.write().unwrap().get_mut(task_id) {
+            t.status = DownloadStatus::Pending;
+            t.stage = None;
+            t.paused_by_schedule = false;
+        }
+        *self
+            .runs
+            .write()
+            .unwrap()
+            .entry(task_id.to_string())
+            .or_insert(0) += 1;
+        self.persist();
+        let _ = app_handle.emit(
+            "download-status-changed",
+            DownloadProgressEvent::new(task_id, task.progress, DownloadStatus::Pending),
+        );
+        true
+    }
+
+    /// Start the scheduler that hands download slots to queued tasks in order
+    pub fn spawn_scheduler(&self) {
+        let manager = self.clone();
+        tauri::async_runtime::spawn(async move {
+            loop {
+                let Ok(permit) = manager.slots.clone().acquire_owned().await else {
+                    return;
+                };
+                // Hold the slot until a task is ready; the timeout re-checks
+                // the schedule and start times
+                let queued = loop {
+                    if let Some(queued) = manager.next_queued() {
+                        break queued;
+                    }
+                    let _ =
+                        tokio::time::timeout(schedule::POLL_INTERVAL, manager.notify.notified())
+                            .await;
+                };
+
+                let slot = DownloadSlot::new(permit, manager.active_downloads.clone());
+                let manager = manager.clone();
+                tauri::async_runtime::spawn(async move {
+                    manager.run_download(queued, slot).await;
+                });
+            }
+        });
+    }
+
+    /// Take the queued task that should start next: highest priority first,
+    /// then queue position. Tasks paused, cancelled or removed while waiting
+    /// are dropped from the queue.
+    fn next_queued(&self) -> Option<QueuedDownload<R>> {
+        if !schedule::is_open(&self.schedule.read().unwrap()) {
+            return None;
+        }
+
+        let tasks = self.tasks.read().unwrap();
+        let mut queue = self.queue.lock().unwrap();
+        queue.retain(|q| {
+            tasks
+                .get(&q.task_id)
+                .is_some_and(|t| t.status == DownloadStatus::Pending)
+        });
+        let next = queue
+            .iter()
+            .enumerate()
+            .filter_map(|(index, q)| {
+                tasks
+                    .get(&q.task_id)
+                    .filter(|t| schedule::is_due(t))
+                    .map(|t| (index, t))
+            })
+            .min_by_key(|(_, t)| (Reverse(t.priority), t.queue_position))
+            .map(|(index, _)| index)?;
+        queue.remove(next)
+    }
+
+    /// Run yt-dlp for a queued task, holding its download slot until it exits
+    async fn run_download(&self, queued: QueuedDownload<R>, slot: DownloadSlot) {
+        let QueuedDownload {
+            task_id,
+            settings,
+            app_handle,
+            cookies_path,
+        } = queued;
+        let Some(task) = self.get_task(&task_id) else {
+            return;
+        };
+
+        let exe_path = self.ytdlp.get_exe_path();
+        let sponsorblock_options = sponsorblock::sponsorblock_options(&task, &settings);
+        let download_dir = settings.download_dir.clone();
         let tasks = self.tasks.clone();
-        let process_ids = self.process_ids.clone();
-        let active_downloads = self.active_downloads.clone();
-        let max_concurrent = self.max_concurrent.clone();
-        let notify = self.notify.clone();
+        let store = self.store.clone();
+        let processes = self.processes.clone();
+        let stop_requests = self.stop_requests.clone();
+        let rate_limit_kib = self.rate_limit_kib.clone();
         let ffmpeg = self.ffmpeg.clone();
         let aria2 = self.aria2.clone();
+        let archive_path = self.archive.get_path().to_string_lossy().to_string();
         let use_cookies = cookies_path.exists();
         let cookies_path_str = cookies_path.to_string_lossy().to_string();
 
-        thread::spawn(move || {
-            // Wait for available slot (dynamic concurrency control)
-            let rt = tokio::runtime::Runtime::new().unwrap();
-            rt.block_on(async {
-                loop {
-                    let current = active_downloads.load(Ordering::SeqCst);
-                    let max = *max_concurrent.read().unwrap();
-
-                    if current < max {
-                        // Try to acquire slot
-                        if active_downloads
-                            .compare_exchange(
-                                current,
-                                current + 1,
-                                Ordering::SeqCst,
-                                Ordering::SeqCst,
-                            )
-                            .is_ok()
-                        {
-                            break;
-                        }
-                        // CAS failed, retry
-                        continue;
-                    }
-                    // No slot available, wait for notification
-                    notify.notified().await;
-                }
-            });
+        // Fetching until yt-dlp reports the first download progress, unless the
+        // task was paused or cancelled after leaving the queue
+        match tasks.write().unwrap().get_mut(&task_id) {
+            Some(t) if t.status == DownloadStatus::Pending => {
+                t.status = DownloadStatus::Fetching;
+                t.stage = None;
+                t.chapter_files.clear();
+            }
+            _ => return,
+        }
+        let run = self.current_run(&task_id);
+        store.request_save();
+
+        let _ = app_handle.emit(
+            "download-status-changed",
+            DownloadProgressEvent::new(&task_id, 0.0, DownloadStatus::Fetching),
+        );
+
+        // Build output filename template, falling back to the default if a saved one is invalid
+        let output_template = template::to_ytdlp(&template::task_template(&task, &settings))
+            .or_else(|_| template::to_ytdlp(template::DEFAULT_TEMPLATE))
+            .unwrap_or_default();
+        let output_template = match &task.options.clip {
+            Some(_) => download_dir.join(clip::with_range_suffix(&output_template)),
+            None => download_dir.join(output_template),
+        };
 
-            // Update status to Downloading (no separate Fetching phase now)
-            if let Some(t) = tasks.write().unwrap().get_mut(&task_id) {
-                t.status = DownloadStatus::Downloading;
+        // Info JSON path with unique task_id to avoid conflicts in concurrent downloads
+        let info_json_path = download_dir.join(format!(".{}.info.json", task_id));
+        let info_json_template = download_dir.join(format!(".{}", task_id));
+        // Final path after merging and post-processing, written by yt-dlp once moved
+        let filepath_path = download_dir.join(format!(".{}.filepath", task_id));
+        let _ = std::fs::remove_file(&filepath_path);
+
+        let output_str = output_template.to_string_lossy().to_string();
+        let info_json_output = info_json_template.to_string_lossy().to_string();
+        let live_options = task.options.live.clone();
+        let mut args = format::format_args(&task, &settings);
+        if let Some(live) = &live_options {
+            args.extend(live::live_args(live));
+        }
+        if let Some(clip) = &task.options.clip {
+            args.extend(clip::clip_args(clip));
+        }
+        args.extend(sponsorblock::sponsorblock_args(
+            &sponsorblock_options,
+            &settings.sponsorblock_api,
+        ));
+        let metadata_options = metadata::metadata_options(&task, &settings);
+        args.extend(metadata::metadata_args(
+            &metadata_options,
+            metadata::output_container(&task, &settings).as_deref(),
+            ffmpeg.get_exe_path().exists(),
+        ));
+        args.extend(subtitles::subtitle_args(
+            &subtitles::subtitle_options(&task, &settings),
+            format::audio_options(&task, &settings).is_some(),
+            ffmpeg.get_exe_path().exists(),
+        ));
+        if task.options.split_chapters && task.options.clip.is_none() {
+            args.extend(Self::split_chapter_args(
+                &output_str,
+                task.options.precise_chapter_cuts,
+            ));
+        }
+        // Share of the total limit, as if every slot were busy
+        let rate_limit = Self::task_rate_limit(
+            *rate_limit_kib.read().unwrap(),
+            *self.max_concurrent.read().unwrap()
+                + self.unslotted_tasks.lock().unwrap().len() as u32,
+            task.options.rate_limit_kib,
+        );
+        if let Some(kib) = rate_limit {
+            args.push("--limit-rate".to_string());
+            args.push(format!("{}K", kib));
+        }
+        args.extend([
+            "--newline".to_string(),
+            "--no-warnings".to_string(),
+            "--progress".to_string(),
+            "--progress-template".to_string(),
+            progress::progress_template(),
+            "--progress-template".to_string(),
+            progress::postprocess_template(),
+            "-o".to_string(),
+            output_str,
+            // Write info.json with unique task_id filename
+            "--write-info-json".to_string(),
+            "--output".to_string(),
+            format!("infojson:{}", info_json_output),
+            "--print-to-file".to_string(),
+            "after_move:%(filepath)s".to_string(),
+            filepath_path.to_string_lossy().to_string(),
+        ]);
+
+        // Record finished videos so they are skipped next time
+        if Self::uses_archive(&task.options) {
+            args.push("--download-archive".to_string());
+            args.push(archive_path);
+        }
+
+        // If ffmpeg is installed locally, specify path
+        let ffmpeg_path_buf = ffmpeg.get_exe_path();
+        if ffmpeg_path_buf.exists() {
+            if let Some(path_str) = ffmpeg_path_buf.to_str() {
+                args.push("--ffmpeg-location".to_string());
+                args.push(path_str.to_string());
             }
+        }
 
-            let _ = app_handle.emit(
-                "download-status-changed",
-                DownloadProgressEvent {
-                    task_id: task_id.clone(),
-                    progress: 0.0,
-                    speed: None,
-                    eta: None,
-                    status: DownloadStatus::Downloading,
-                    downloaded_bytes: None,
-                    total_bytes: None,
-                },
-            );
+        // Add cookies if available
+        if use_cookies {
+            args.push("--cookies".to_string());
+            args.push(cookies_path_str);
+        }
 
-            // Build output filename template
-            let output_template = download_dir.join("%(title)s.%(ext)s");
+        // Add --no-playlist if URL contains a video ID
+        if Self::should_use_no_playlist(&task.url) {
+            args.push("--no-playlist".to_string());
+        }
 
-            // Info JSON path with unique task_id to avoid conflicts in concurrent downloads
-            let info_json_path = download_dir.join(format!(".{}.info.json", task_id));
-            let info_json_template = download_dir.join(format!(".{}", task_id));
+        // Use aria2 as external downloader if available (faster multi-connection download)
+        // Only for http/https downloads, not HLS fragments (which have their own progress format)
+        // Live streams are recorded by ffmpeg or the native HLS downloader
+        let aria2_path = aria2.get_exe_path();
+        if aria2_path.exists() && live_options.is_none() {
+            // Use aria2 only for http/https protocols, not for m3u8/HLS
+            args.push("--downloader".to_string());
+            args.push("http,https:aria2c".to_string());
+            args.push("--downloader-args".to_string());
+            // -x: max connections per server, -s: split file into segments
+            // aria2c ignores --limit-rate, so the limit is passed to it directly
+            let mut aria2_args =
+                "aria2c:-x 16 -s 16 --file-allocation=none --summary-interval=1".to_string();
+            if let Some(kib) = rate_limit {
+                aria2_args.push_str(&format!(" --max-download-limit={}K", kib));
+            }
+            args.push(aria2_args);
+            // Add aria2c directory to PATH so yt-dlp can find it
+            if let Some(parent) = aria2_path.parent() {
+                let current_path = std::env::var("PATH").unwrap_or_default();
+                std::env::set_var(
+                    "PATH",
+                    format!("{};{}", parent.to_string_lossy(), current_path),
+                );
+            }
+        }
 
-            // Build resolution argument
-            let format_arg = match task.resolution.as_str() {
-                "best" => "bestvideo[ext=mp4]+bestaudio[ext=m4a]/best[ext=mp4]/best".to_string(),
-                "2160p" | "4K" => {
-                    "bestvideo[height<=2160][ext=mp4]+bestaudio[ext=m4a]/best[height<=2160]"
-                        .to_string()
-                }
-                "1440p" | "2K" => {
-                    "bestvideo[height<=1440][ext=mp4]+bestaudio[ext=m4a]/best[height<=1440]"
-                        .to_string()
-                }
-                "1080p" => "bestvideo[height<=1080][ext=mp4]+bestaudio[ext=m4a]/best[height<=1080]"
-                    .to_string(),
-                "720p" => "bestvideo[height<=720][ext=mp4]+bestaudio[ext=m4a]/best[height<=720]"
-                    .to_string(),
-                "480p" => "bestvideo[height<=480][ext=mp4]+bestaudio[ext=m4a]/best[height<=480]"
-                    .to_string(),
-                "360p" => "bestvideo[height<=360][ext=mp4]+bestaudio[ext=m4a]/best[height<=360]"
-                    .to_string(),
-                "audio" => "bestaudio[ext=m4a]/bestaudio".to_string(),
-                _ => "bestvideo[height<=1080][ext=mp4]+bestaudio[ext=m4a]/best[height<=1080]"
-                    .to_string(),
-            };
+        args.push(task.url.clone());
 
-            let output_str = output_template.to_string_lossy().to_string();
-            let info_json_output = info_json_template.to_string_lossy().to_string();
-            let mut args = vec![
-                "-f".to_string(),
-                format_arg,
-                "--newline".to_string(),
-                "--no-warnings".to_string(),
-                "--progress".to_string(),
-                "-o".to_string(),
-                output_str,
-                // Write info.json with unique task_id filename
-                "--write-info-json".to_string(),
-                "--output".to_string(),
-                format!("infojson:{}", info_json_output),
-            ];
-
-            // If ffmpeg is installed locally, specify path
-            let ffmpeg_path_buf = ffmpeg.get_exe_path();
-            if ffmpeg_path_buf.exists() {
-                if let Some(path_str) = ffmpeg_path_buf.to_str() {
-                    args.push("--ffmpeg-location".to_string());
-                    args.push(path_str.to_string());
+        // A paused process may still be writing its .part file
+        while processes.pid(&task_id).is_some() {
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+        Self::track_temp_files(
+            &tasks,
+            &store,
+            &task_id,
+            cleanup::sidecars(&download_dir, &task_id),
+        );
+
+        let mut cmd = tokio::process::Command::new(&exe_path);
+        cmd.args(&args)
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped());
+        process::configure(&mut cmd);
+
+        let mut child = match cmd.spawn() {
+            Ok(c) => c,
+            Err(e) => {
+                if let Some(t) = tasks.write().unwrap().get_mut(&task_id) {
+                    t.status = DownloadStatus::Failed;
+                    t.error = Some(format!("Failed to start download: {}", e));
                 }
+                store.request_save();
+                return;
             }
+        };
 
-            // Add cookies if available
-            if use_cookies {
-                args.push("--cookies".to_string());
-                args.push(cookies_path_str);
-            }
+        // Store the process tree for pausing and cancellation
+        let pid = child.id();
+        if let Some(tree) = ProcessTree::attach(&child) {
+            processes.insert(task_id.clone(), tree);
+        }
+        // A pause or cancel while the process was starting found nothing to stop
+        let stopped = tasks.read().unwrap().get(&task_id).is_some_and(|t| {
+            matches!(t.status, DownloadStatus::Paused | DownloadStatus::Cancelled)
+        });
+        if stopped {
+            processes.kill(&task_id);
+        }
 
-            // Add --no-playlist if URL contains a video ID
-            if DownloadManager::should_use_no_playlist(&task.url) {
-                args.push("--no-playlist".to_string());
+        // Collect the last ERROR line from stderr for the task's error message.
+        // Live recordings and clips handed to ffmpeg report their progress here too.
+        let stderr = child.stderr.take().unwrap();
+        let last_error: Arc<Mutex<Option<String>>> = Arc::new(Mutex::new(None));
+        let last_error_clone = last_error.clone();
+        let stderr_task = {
+            let is_live = live_options.is_some();
+            let clip_options = task.options.clip.clone();
+            let tasks = tasks.clone();
+            let store = store.clone();
+            let app_handle = app_handle.clone();
+            let task_id = task_id.clone();
+            tauri::async_runtime::spawn(async move {
+                // ffmpeg restarts its clock for every section
+                let mut sections_done = 0.0;
+                let mut last_time = 0.0;
+
+                let mut lines = progress::LineReader::new(stderr);
+                while let Some(line) = lines.next_line().await {
+                    if let Some(update) = progress::parse_ffmpeg_line(&line) {
+                        if is_live {
+                            Self::record_progress(&tasks, &store, &app_handle, &task_id, &update);
+                            continue;
+                        }
+                        if let Some(clip) = &clip_options {
+                            let time = update.elapsed.unwrap_or(0.0);
+                            if time < last_time {
+                                sections_done += last_time;
+                            }
+                            last_time = time;
+
+                            let info = tasks
+                                .read()
+                                .unwrap()
+                                .get(&task_id)
+                                .and_then(|t| t.video_info.clone());
+                            let percent = clip::clip_duration(clip, info.as_ref())
+                                .map(|total| ((sections_done + time) / total * 100.0).min(100.0));
+                            Self::clip_progress(
+                                &tasks,
+                                &store,
+                                &app_handle,
+                                &task_id,
+                                percent,
+                                &update,
+                            );
+                            continue;
+                        }
+                    }
+                    if let Some(message) = line.strip_prefix("ERROR:") {
+                        *last_error_clone.lock().unwrap() = Some(message.trim().to_string());
+                    }
+                }
+            })
+        };
+
+        // Parse progress output
+        let stdout = child.stdout.take().unwrap();
+        let mut lines = progress::LineReader::new(stdout);
+
+        // Check for "already downloaded" or "has already been downloaded"
+        let already_regex =
+            Regex::new(r"(?i)(already\s+(been\s+)?downloaded|has already been recorded)").unwrap();
+
+        // Flag to track if we've loaded the info.json
+        let mut info_loaded = false;
+        let info_json_path_clone = info_json_path.clone();
+        let tasks_clone = tasks.clone();
+        let app_handle_clone = app_handle.clone();
+        let task_id_clone = task_id.clone();
+
+        // Current lifecycle stage, to emit status changes only on transitions
+        let mut current_status = DownloadStatus::Fetching;
+        let mut current_stage: Option<String> = None;
+
+        while let Some(line) = lines.next_line().await {
+            // A paused run still draining its output after the task was resumed
+            if self.current_run(&task_id) != run {
+                continue;
             }
 
-            // Use aria2 as external downloader if available (faster multi-connection download)
-            // Only for http/https downloads, not HLS fragments (which have their own progress format)
-            let aria2_path = aria2.get_exe_path();
-            if aria2_path.exists() {
-                // Use aria2 only for http/https protocols, not for m3u8/HLS
-                args.push("--downloader".to_string());
-                args.push("http,https:aria2c".to_string());
-                args.push("--downloader-args".to_string());
-                // -x: max connections per server, -s: split file into segments
-                args.push(
-                    "aria2c:-x 16 -s 16 --file-allocation=none --summary-interval=1".to_string(),
-                );
-                // Add aria2c directory to PATH so yt-dlp can find it
-                if let Some(parent) = aria2_path.parent() {
-                    let current_path = std::env::var("PATH").unwrap_or_default();
-                    std::env::set_var(
-                        "PATH",
-                        format!("{};{}", parent.to_string_lossy(), current_path),
-                    );
+            // Try to load video info from .info.json if not loaded yet
+            if !info_loaded && info_json_path_clone.exists() {
+                if let Ok(content) = std::fs::read_to_string(&info_json_path_clone) {
+                    if let Ok(json) = serde_json::from_str::<serde_json::Value>(&content) {
+                        let mut video_info = Self::parse_video_info(&json);
+
+                        // yt-dlp records the rendered output template in the info dict
+                        let rendered_path = json["_filename"]
+                            .as_str()
+                            .or(json["filename"].as_str())
+                            .map(PathBuf::from);
+
+                        // Update task with video info, keeping probed formats if the
+                        // sidecar has none
+                        if let Some(t) = tasks_clone.write().unwrap().get_mut(&task_id_clone) {
+                            if rendered_path.is_some() {
+                                t.output_path = rendered_path;
+                            }
+                            if let Some(probed) = t.video_info.take() {
+                                if video_info.formats.is_empty() {
+                                    video_info.formats = probed.formats;
+                                }
+                                if video_info.subtitles.is_empty() {
+                                    video_info.subtitles = probed.subtitles;
+                                }
+                                if video_info.chapters.is_empty() {
+                                    video_info.chapters = probed.chapters;
+                                }
+                                video_info.playlist_index =
+                                    video_info.playlist_index.or(probed.playlist_index);
+                                video_info.playlist_count =
+                                    video_info.playlist_count.or(probed.playlist_count);
+                            }
+                            t.video_info = Some(video_info);
+                        }
+                        store.request_save();
+
+                        // Emit update to frontend
+                        let _ = app_handle_clone.emit("task-info-updated", &task_id_clone);
+                        info_loaded = true;
+                    }
                 }
             }
 
-            args.push(task.url.clone());
+            if let Some(destination) = cleanup::destination(&line) {
+                Self::track_temp_files(&tasks, &store, &task_id, [destination]);
+            }
+            if let Some(chapter_file) = Self::chapter_destination(&line) {
+                if let Some(t) = tasks.write().unwrap().get_mut(&task_id) {
+                    t.chapter_files.push(chapter_file);
+                }
+            }
 
-            let mut cmd = Command::new(&exe_path);
-            cmd.args(&args)
-                .stdout(Stdio::piped())
-                .stderr(Stdio::piped());
+            // Check if file already exists
+            if already_regex.is_match(&line) {
+                if let Some(t) = tasks.write().unwrap().get_mut(&task_id) {
+                    t.progress = 100.0;
+                    t.status = DownloadStatus::Completed;
+                }
+                store.request_save();
+                let _ = app_handle.emit(
+                    "download-progress",
+                    DownloadProgressEvent {
+                        eta: Some("Already downloaded".to_string()),
+                        ..DownloadProgressEvent::new(&task_id, 100.0, DownloadStatus::Completed)
+                    },
+                );
+                continue;
+            }
 
-            #[cfg(target_os = "windows")]
-            cmd.creation_flags(CREATE_NO_WINDOW);
+            // Postprocessing stages: each stage restarts progress at 0
+            if let Some(stage) = progress::parse_stage_line(&line) {
+                let progress = if stage.finished { 100.0 } else { 0.0 };
+                let entering = current_stage.as_deref() != Some(stage.name.as_str());
+                if !entering && !stage.finished {
+                    continue;
+                }
 
-            let mut child = match cmd.spawn() {
-                Ok(c) => c,
-                Err(e) => {
-                    if let Some(t) = tasks.write().unwrap().get_mut(&task_id) {
-                        t.status = DownloadStatus::Failed;
-                        t.error = Some(format!("Failed to start download: {}", e));
-                    }
-                    return;
+                current_status = stage.status.clone();
+                current_stage = Some(stage.name.clone());
+                if let Some(t) = tasks.write().unwrap().get_mut(&task_id) {
+                    t.status = stage.status.clone();
+                    t.stage = Some(stage.name.clone());
+                    t.progress = progress;
+                    t.speed = None;
+                    t.eta = None;
+                }
+                if entering {
+                    store.request_save();
                 }
-            };
 
-            // Store process ID for cancellation
-            process_ids
-                .write()
-                .unwrap()
-                .insert(task_id.clone(), child.id());
-
-            // Parse progress output
-            let stdout = child.stdout.take().unwrap();
-            let reader = BufReader::new(stdout);
-
-            // yt-dlp output formats:
-            // Progress: [download]  45.2% of 100MiB at 5.23MiB/s ETA 00:10
-            // Complete: [download] 100% of   31.76MiB in 00:00:01 at 19.91MiB/s
-            let progress_regex = Regex::new(r"\[download\]\s*([\d.]+)%").unwrap();
-            let speed_regex = Regex::new(r"at\s+([\d.]+\s*\w+/s)").unwrap();
-            let eta_regex = Regex::new(r"ETA\s+(\S+)").unwrap();
-            // Check for "already downloaded" or "has already been downloaded"
-            let already_regex =
-                Regex::new(r"(?i)(already\s+(been\s+)?downloaded|has already been recorded)")
-                    .unwrap();
-
-            // Flag to track if we've loaded the info.json
-            let mut info_loaded = false;
-            let info_json_path_clone = info_json_path.clone();
-            let tasks_clone = tasks.clone();
-            let app_handle_clone = app_handle.clone();
-            let task_id_clone = task_id.clone();
-
-            for line in reader.lines() {
-                // Try to load video info from .info.json if not loaded yet
-                if !info_loaded && info_json_path_clone.exists() {
-                    if let Ok(content) = std::fs::read_to_string(&info_json_path_clone) {
-                        if let Ok(json) = serde_json::from_str::<serde_json::Value>(&content) {
-                            let video_info = VideoInfo {
-                                id: json["id"].as_str().unwrap_or("unknown").to_string(),
-                                url: json["webpage_url"]
-                                    .as_str()
-                                    .map(|s| s.to_string())
-                                    .unwrap_or_default(),
-                                title: json["title"]
-                                    .as_str()
-                                    .unwrap_or("Unknown Title")
-                                    .to_string(),
-                                duration: json["duration"].as_u64(),
-                                duration_string: json["duration_string"]
-                                    .as_str()
-                                    .map(|s| s.to_string()),
-                                thumbnail: json["thumbnail"].as_str().map(|s| s.to_string()),
-                                uploader: json["uploader"].as_str().map(|s| s.to_string()),
-                                view_count: json["view_count"].as_u64(),
-                                formats: vec![],
-                                playlist_index: None,
-                                playlist_count: None,
-                            };
-
-                            // Update task with video info
-                            if let Some(t) = tasks_clone.write().unwrap().get_mut(&task_id_clone) {
-                                t.video_info = Some(video_info);
-                            }
+                let _ = app_handle.emit(
+                    "download-status-changed",
+                    DownloadProgressEvent {
+                        stage: Some(stage.name),
+                        ..DownloadProgressEvent::new(&task_id, progress, stage.status)
+                    },
+                );
+                continue;
+            }
 
-                            // Emit update to frontend
-                            let _ = app_handle_clone.emit("task-info-updated", &task_id_clone);
-                            info_loaded = true;
-                        }
-                    }
+            // Waiting for a scheduled stream or premiere to start
+            if live_options.is_some()
+                && line.starts_with("[wait]")
+                && current_stage.as_deref() != Some("Waiting")
+            {
+                current_stage = Some("Waiting".to_string());
+                if let Some(t) = tasks.write().unwrap().get_mut(&task_id) {
+                    t.stage = current_stage.clone();
                 }
+                let _ = app_handle.emit(
+                    "download-status-changed",
+                    DownloadProgressEvent {
+                        stage: current_stage.clone(),
+                        ..DownloadProgressEvent::new(&task_id, 0.0, DownloadStatus::Fetching)
+                    },
+                );
+                continue;
+            }
 
-                if let Ok(line) = line {
-                    // DEBUG: Print all output lines to console
-                    println!("[yt-dlp] {}", line);
+            // Parse download progress (yt-dlp progress template or aria2c summary)
+            let update =
+                progress::parse_ytdlp_line(&line).or_else(|| progress::parse_aria2_line(&line));
+            if let Some(update) = update {
+                if live_options.is_some() {
+                    current_status = DownloadStatus::Recording;
+                    current_stage = None;
+                    Self::record_progress(&tasks, &store, &app_handle, &task_id, &update);
+                    continue;
+                }
 
-                    // Check if file already exists
-                    if already_regex.is_match(&line) {
-                        if let Some(t) = tasks.write().unwrap().get_mut(&task_id) {
-                            t.progress = 100.0;
-                            t.status = DownloadStatus::Completed;
-                        }
-                        let _ = app_handle.emit(
-                            "download-progress",
-                            DownloadProgressEvent {
-                                task_id: task_id.clone(),
-                                progress: 100.0,
-                                speed: None,
-                                eta: Some("Already downloaded".to_string()),
-                                status: DownloadStatus::Completed,
-                                downloaded_bytes: None,
-                                total_bytes: None,
-                            },
-                        );
-                        continue;
+                // Back to downloading after fetching or between formats
+                if current_status != DownloadStatus::Downloading {
+                    current_status = DownloadStatus::Downloading;
+                    current_stage = None;
+                    if let Some(t) = tasks.write().unwrap().get_mut(&task_id) {
+                        t.status = DownloadStatus::Downloading;
+                        t.stage = None;
                     }
+                    store.request_save();
+                    let _ = app_handle.emit(
+                        "download-status-changed",
+                        DownloadProgressEvent::new(
+                            &task_id,
+                            update.percent.unwrap_or(0.0),
+                            DownloadStatus::Downloading,
+                        ),
+                    );
+                }
 
-                    // Parse download progress
-                    if let Some(caps) = progress_regex.captures(&line) {
-                        if let Some(progress_str) = caps.get(1) {
-                            if let Ok(progress) = progress_str.as_str().parse::<f64>() {
-                                let speed = speed_regex
-                                    .captures(&line)
-                                    .and_then(|c| c.get(1))
-                                    .map(|m| m.as_str().to_string());
-
-                                let eta = eta_regex
-                                    .captures(&line)
-                                    .and_then(|c| c.get(1))
-                                    .map(|m| m.as_str().to_string());
-
-                                // Update task progress
-                                if let Some(t) = tasks.write().unwrap().get_mut(&task_id) {
-                                    t.progress = progress;
-                                    t.speed = speed.clone();
-                                    t.eta = eta.clone();
-                                }
+                let speed = update.speed.map(progress::format_speed);
+                let eta = update.eta.map(progress::format_eta);
 
-                                // Emit progress event
-                                let _ = app_handle.emit(
-                                    "download-progress",
-                                    DownloadProgressEvent {
-                                        task_id: task_id.clone(),
-                                        progress,
-                                        speed,
-                                        eta,
-                                        status: DownloadStatus::Downloading,
-                                        downloaded_bytes: None,
-                                        total_bytes: None,
-                                    },
-                                );
+                // Update task progress
+                let progress = {
+                    let mut tasks = tasks.write().unwrap();
+                    match tasks.get_mut(&task_id) {
+                        Some(t) => {
+                            if let Some(percent) = update.percent {
+                                t.progress = percent;
                             }
+                            t.speed = speed.clone();
+                            t.eta = eta.clone();
+                            t.downloaded_bytes = update.downloaded_bytes;
+                            if update.total_bytes.is_some() {
+                                t.total_bytes = update.total_bytes;
+                            }
+                            t.progress
                         }
+                        None => update.percent.unwrap_or(0.0),
                     }
-                }
+                };
+
+                // Emit progress event
+                let _ = app_handle.emit(
+                    "download-progress",
+                    DownloadProgressEvent {
+                        speed,
+                        eta,
+                        downloaded_bytes: update.downloaded_bytes,
+                        total_bytes: update.total_bytes,
+                        speed_bytes: update.speed,
+                        eta_secs: update.eta,
+                        fragment_index: update.fragment_index,
+                        fragment_count: update.fragment_count,
+                        ..DownloadProgressEvent::new(
+                            &task_id,
+                            progress,
+                            DownloadStatus::Downloading,
+                        )
+                    },
+                );
             }
+        }
 
-            // Wait for process to finish
-            match child.wait() {
-                Ok(status) => {
-                    // Remove from process_ids since process has ended
-                    process_ids.write().unwrap().remove(&task_id);
+        // Wait for process to finish
+        let mut retry_after: Option<Duration> = None;
+        let wait_result = child.wait().await;
+        let _ = stderr_task.await;
+        // Remove from the process table since process has ended
+        if let Some(pid) = pid {
+            processes.remove(&task_id, pid);
+        }
+        // The task was resumed before this run exited. The newer run owns its
+        // status and sidecar files now.
+        if self.current_run(&task_id) != run {
+            return;
+        }
+        match wait_result {
+            Ok(status) => {
+                // Check if the task was already paused or cancelled (by user action)
+                let current_status = tasks
+                    .read()
+                    .unwrap()
+                    .get(&task_id)
+                    .map(|t| t.status.clone());
+
+                // If already paused or cancelled, don't override the status
+                if current_status == Some(DownloadStatus::Paused)
+                    || current_status == Some(DownloadStatus::Cancelled)
+                {
+                    // Task was intentionally stopped, emit the current status
+                    let _ = app_handle.emit(
+                        "download-progress",
+                        DownloadProgressEvent::new(
+                            &task_id,
+                            tasks
+                                .read()
+                                .unwrap()
+                                .get(&task_id)
+                                .map(|t| t.progress)
+                                .unwrap_or(0.0),
+                            current_status.unwrap_or(DownloadStatus::Paused),
+                        ),
+                    );
+                    return;
+                }
 
-                    // Check if the task was already paused or cancelled (by user action)
-                    let current_status = tasks
-                        .read()
-                        .unwrap()
-                        .get(&task_id)
-                        .map(|t| t.status.clone());
-
-                    // If already paused or cancelled, don't override the status
-                    if current_status == Some(DownloadStatus::Paused)
-                        || current_status == Some(DownloadStatus::Cancelled)
-                    {
-                        // Task was intentionally stopped, emit the current status
-                        let _ = app_handle.emit(
-                            "download-progress",
-                            DownloadProgressEvent {
-                                task_id: task_id.clone(),
-                                progress: tasks
-                                    .read()
-                                    .unwrap()
-                                    .get(&task_id)
-                                    .map(|t| t.progress)
-                                    .unwrap_or(0.0),
-                                speed: None,
-                                eta: None,
-                                status: current_status.unwrap_or(DownloadStatus::Paused),
-                                downloaded_bytes: None,
-                                total_bytes: None,
-                            },
-                        );
-                        return;
-                    }
+                // A recording stopped by the user exits with an error after
+                // ffmpeg has finalized the file
+                let stopped = stop_requests.write().unwrap().remove(&task_id);
+
+                if status.success() || stopped {
+                    let final_path =
+                        std::fs::read_to_string(&filepath_path)
+                            .ok()
+                            .and_then(|content| {
+                                content
+                                    .lines()
+                                    .rev()
+                                    .find(|l| !l.trim().is_empty())
+                                    .map(|l| PathBuf::from(l.trim()))
+                            });
+                    // Report what SponsorBlock cut, from the segments yt-dlp
+                    // recorded in the info.json
+                    let removed = if sponsorblock_options.remove.is_empty() {
+                        None
+                    } else {
+                        std::fs::read_to_string(&info_json_path)
+                            .ok()
+                            .and_then(|c| serde_json::from_str(&c).ok())
+                            .map(|json| {
+                                sponsorblock::removed_segments(&json, &sponsorblock_options)
+                            })
+                    };
 
-                    if status.success() {
-                        if let Some(t) = tasks.write().unwrap().get_mut(&task_id) {
-                            t.status = DownloadStatus::Completed;
-                            t.progress = 100.0;
+                    if let Some(t) = tasks.write().unwrap().get_mut(&task_id) {
+                        if final_path.is_some() {
+                            t.output_path = final_path;
                         }
-                        let _ = app_handle.emit(
-                            "download-progress",
-                            DownloadProgressEvent {
-                                task_id: task_id.clone(),
-                                progress: 100.0,
-                                speed: None,
-                                eta: None,
-                                status: DownloadStatus::Completed,
-                                downloaded_bytes: None,
-                                total_bytes: None,
-                            },
-                        );
-                    } else {
-                        if let Some(t) = tasks.write().unwrap().get_mut(&task_id) {
-                            t.status = DownloadStatus::Failed;
-                            t.error = Some("Download failed".to_string());
+                        if let Some((segments, saved)) = removed {
+                            t.removed_segments = segments;
+                            t.time_saved_secs = Some(saved);
                         }
-                        let _ = app_handle.emit(
-                            "download-progress",
-                            DownloadProgressEvent {
-                                task_id: task_id.clone(),
-                                progress: 0.0,
-                                speed: None,
-                                eta: None,
-                                status: DownloadStatus::Failed,
-                                downloaded_bytes: None,
-                                total_bytes: None,
-                            },
-                        );
                     }
-                }
-                Err(e) => {
-                    // Remove from process_ids since process has ended
-                    process_ids.write().unwrap().remove(&task_id);
 
-                    // Check if the task was already paused or cancelled
-                    let current_status = tasks
-                        .read()
-                        .unwrap()
-                        .get(&task_id)
-                        .map(|t| t.status.clone());
-
-                    if current_status == Some(DownloadStatus::Paused)
-                        || current_status == Some(DownloadStatus::Cancelled)
-                    {
-                        // Clean up info.json on pause/cancel
-                        let _ = std::fs::remove_file(&info_json_path);
-                        // Release slot before returning
-                        active_downloads.fetch_sub(1, Ordering::SeqCst);
-                        notify.notify_waiters();
-                        return;
+                    if let Some(t) = tasks.write().unwrap().get_mut(&task_id) {
+                        t.status = DownloadStatus::Completed;
+                        t.stage = None;
+                        t.progress = 100.0;
+                        t.temp_files.clear();
                     }
+                    let _ = app_handle.emit(
+                        "download-progress",
+                        DownloadProgressEvent::new(&task_id, 100.0, DownloadStatus::Completed),
+                    );
+                } else {
+                    let error = last_error
+                        .lock()
+                        .unwrap()
+                        .take()
+                        .unwrap_or_else(|| "Download failed".to_string());
 
+                    // Schedule an automatic retry for transient failures
+                    let mut status = DownloadStatus::Failed;
                     if let Some(t) = tasks.write().unwrap().get_mut(&task_id) {
-                        t.status = DownloadStatus::Failed;
-                        t.error = Some(format!("Process error: {}", e));
+                        if settings.auto_retry
+                            && t.retry_count < settings.max_retries
+                            && Self::is_transient_error(&error)
+                        {
+                            t.retry_count += 1;
+                            status = DownloadStatus::Pending;
+                            retry_after = Some(Duration::from_secs(
+                                settings.retry_backoff_secs << (t.retry_count - 1).min(16),
+                            ));
+                        }
+                        t.status = status.clone();
+                        t.stage = None;
+                        t.speed = None;
+                        t.eta = None;
+                        t.error = Some(error);
                     }
+                    let _ = app_handle.emit(
+                        "download-progress",
+                        DownloadProgressEvent::new(&task_id, 0.0, status),
+                    );
+                }
+            }
+            Err(e) => {
+                // Check if the task was already paused or cancelled
+                let current_status = tasks
+                    .read()
+                    .unwrap()
+                    .get(&task_id)
+                    .map(|t| t.status.clone());
+
+                if current_status == Some(DownloadStatus::Paused)
+                    || current_status == Some(DownloadStatus::Cancelled)
+                {
+                    // Clean up info.json on pause/cancel
+                    let _ = std::fs::remove_file(&info_json_path);
+                    let _ = std::fs::remove_file(&filepath_path);
+                    return;
+                }
+
+                if let Some(t) = tasks.write().unwrap().get_mut(&task_id) {
+                    t.status = DownloadStatus::Failed;
+                    t.error = Some(format!("Process error: {}", e));
                 }
             }
+        }
+
+        store.request_save();
+
+        // Keep the .info.json next to a completed file if requested, otherwise clean it up
+        let completed_path = tasks
+            .read()
+            .unwrap()
+            .get(&task_id)
+            .filter(|t| t.status == DownloadStatus::Completed)
+            .and_then(|t| t.output_path.clone());
+        match completed_path.filter(|_| metadata_options.write_info_json) {
+            Some(output_path) => {
+                let _ = std::fs::rename(&info_json_path, output_path.with_extension("info.json"));
+            }
+            None => {
+                let _ = std::fs::remove_file(&info_json_path);
+            }
+        }
+        let _ = std::fs::remove_file(&filepath_path);
+
+        // Release download slot so the scheduler can start the next task
+        drop(slot);
+
+        // Re-enter the queue after the backoff unless the user intervened meanwhile
+        if let Some(delay) = retry_after {
+            tokio::time::sleep(delay).await;
+            if self.get_task(&task_id).map(|t| t.status) == Some(DownloadStatus::Pending) {
+                self.start_download(task_id, settings, app_handle, cookies_path);
+            }
+        }
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use std::os::unix::fs::PermissionsExt;
+    use std::time::Instant;
+    use tauri::test::{mock_app, MockRuntime};
+
+    struct Fixture {
+        manager: DownloadManager<MockRuntime>,
+        settings: AppSettings,
+        app_handle: AppHandle<MockRuntime>,
+        dir: PathBuf,
+    }
+
+    impl Fixture {
+        /// A manager with two slots whose yt-dlp is a shell script running `script`
+        fn new(script: &str) -> Self {
+            let dir = std::env::temp_dir().join(format!("vivid-down-test-{}", Uuid::new_v4()));
+            let ytdlp = Arc::new(YtDlpManager::new(dir.clone()));
+            let exe_path = ytdlp.get_exe_path();
+            std::fs::write(&exe_path, format!("#!/bin/sh\n{}\n", script)).unwrap();
+            std::fs::set_permissions(&exe_path, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+            let manager = DownloadManager::new(
+                dir.clone(),
+                ytdlp,
+                Arc::new(FFmpegManager::new(dir.clone())),
+                Arc::new(Aria2Manager::new(dir.clone())),
+                2,
+                0,
+                ScheduleOptions::default(),
+            );
+            manager.spawn_scheduler();
+
+            Self {
+                manager,
+                settings: AppSettings {
+                    download_dir: dir.join("downloads"),
+                    ..AppSettings::default()
+                },
+                app_handle: mock_app().handle().clone(),
+                dir,
+            }
+        }
+
+        fn start(&self, count: usize) -> Vec<String> {
+            (0..count)
+                .map(|i| {
+                    let task = self
+                        .manager
+                        .create_task(
+                            format!("https://example.com/video/{}", i),
+                            "best".to_string(),
+                            TaskOptions::default(),
+                        )
+                        .unwrap();
+                    self.manager.start_download(
+                        task.id.clone(),
+                        self.settings.clone(),
+                        self.app_handle.clone(),
+                        self.dir.join("cookies.txt"),
+                    );
+                    task.id
+                })
+                .collect()
+        }
+
+        fn active(&self) -> u32 {
+            self.manager.active_downloads.load(Ordering::SeqCst)
+        }
+
+        fn running(&self) -> usize {
+            self.manager.processes.count()
+        }
+
+        fn status(&self, task_id: &str) -> DownloadStatus {
+            self.manager.get_task(task_id).unwrap().status
+        }
+    }
+
+    impl Drop for Fixture {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.dir);
+        }
+    }
+
+    fn wait_until(what: &str, condition: impl Fn() -> bool) {
+        let deadline = Instant::now() + Duration::from_secs(10);
+        while !condition() {
+            assert!(Instant::now() < deadline, "timed out waiting for {}", what);
+            std::thread::sleep(Duration::from_millis(20));
+        }
+    }
+
+    #[test]
+    fn pause_releases_slots() {
+        let fixture = Fixture::new("exec sleep 30");
+        let ids = fixture.start(3);
+
+        // Pausing and resuming repeatedly must not use up the slots
+        for _ in 0..3 {
+            wait_until("two downloads to run", || fixture.running() == 2);
+            assert_eq!(fixture.active(), 2);
+            for id in &ids {
+                fixture.manager.pause_download(id);
+            }
+            wait_until("slots to be released", || fixture.active() == 0);
+            assert!(ids
+                .iter()
+                .all(|id| fixture.status(id) == DownloadStatus::Paused));
+
+            for id in &ids {
+                fixture.manager.start_download(
+                    id.clone(),
+                    fixture.settings.clone(),
+                    fixture.app_handle.clone(),
+                    fixture.dir.join("cookies.txt"),
+                );
+            }
+        }
+
+        // Resuming before the paused processes have exited must not let the
+        // old runs mark the tasks as failed
+        wait_until("two downloads to run", || fixture.running() == 2);
+        let running: Vec<(String, u32)> = ids
+            .iter()
+            .filter_map(|id| Some((id.clone(), fixture.manager.processes.pid(id)?)))
+            .collect();
+        for (id, _) in &running {
+            fixture.manager.pause_download(id);
+            fixture.manager.start_download(
+                id.clone(),
+                fixture.settings.clone(),
+                fixture.app_handle.clone(),
+                fixture.dir.join("cookies.txt"),
+            );
+        }
+        wait_until("the paused processes to be replaced", || {
+            fixture.running() == 2
+                && running
+                    .iter()
+                    .all(|(id, old_pid)| fixture.manager.processes.pid(id) != Some(*old_pid))
+        });
+        for id in &ids {
+            let task = fixture.manager.get_task(id).unwrap();
+            assert_ne!(task.status, DownloadStatus::Failed);
+            assert_eq!(task.error, None);
+        }
+
+        for id in &ids {
+            fixture.manager.cancel_download(id, false);
+        }
+        wait_until("slots to be released", || fixture.active() == 0);
+    }
+
+    #[test]
+    fn cancel_releases_slots() {
+        let fixture = Fixture::new("exec sleep 30");
+        let ids = fixture.start(3);
+
+        wait_until("two downloads to run", || fixture.running() == 2);
+        for id in &ids {
+            fixture.manager.cancel_download(id, false);
+        }
+        wait_until("slots to be released", || fixture.active() == 0);
+        assert!(ids
+            .iter()
+            .all(|id| fixture.status(id) == DownloadStatus::Cancelled));
+    }
+
+    /// Whether a process exists and hasn't exited (zombies may not be reaped in containers)
+    fn alive(pid: &str) -> bool {
+        let output = Command::new("ps")
+            .args(["-o", "stat=", "-p", pid])
+            .output()
+            .unwrap();
+        let stat = String::from_utf8_lossy(&output.stdout);
+        !stat.trim().is_empty() && !stat.trim().starts_with('Z')
+    }
+
+    #[test]
+    fn cancel_kills_child_processes() {
+        // Stands in for yt-dlp starting aria2c or ffmpeg
+        let fixture = Fixture::new("sleep 30 &\necho $! > \"$0.child\"\nwait");
+        let child_file = fixture
+            .manager
+            .ytdlp
+            .get_exe_path()
+            .with_extension("exe.child");
+        let ids = fixture.start(1);
+
+        wait_until("the child process to start", || {
+            std::fs::read_to_string(&child_file).is_ok_and(|pid| !pid.trim().is_empty())
+        });
+        let child_pid = std::fs::read_to_string(&child_file).unwrap();
+        assert!(alive(child_pid.trim()));
+
+        fixture.manager.cancel_download(&ids[0], false);
+        wait_until("the child process to be killed", || {
+            !alive(child_pid.trim())
+        });
+        wait_until("slots to be released", || fixture.active() == 0);
+    }
+
+    #[test]
+    fn cancel_removes_temp_files() {
+        let fixture = Fixture::new(
+            r#"d="$(dirname "$0")/../downloads"
+touch "$d/video.f1.mp4" "$d/video.f2.webm.part" "$d/video.f2.webm.ytdl"
+echo "[download] Destination: $d/video.f1.mp4"
+echo "[download] Destination: $d/video.f2.webm"
+exec sleep 30"#,
+        );
+        let download_dir = fixture.settings.download_dir.clone();
+        std::fs::create_dir_all(&download_dir).unwrap();
+        let unrelated = download_dir.join("other.mp4.part");
+        std::fs::write(&unrelated, "").unwrap();
+        let ids = fixture.start(1);
+
+        wait_until("destinations to be tracked", || {
+            fixture.manager.get_task(&ids[0]).unwrap().temp_files.len() == 4
+        });
+        let info_json = download_dir.join(format!(".{}.info.json", ids[0]));
+        std::fs::write(&info_json, "{}").unwrap();
+
+        fixture.manager.cancel_download(&ids[0], false);
+        wait_until("temp files to be removed", || {
+            std::fs::read_dir(&download_dir).unwrap().count() == 1
+        });
+        assert!(unrelated.exists());
+        assert!(fixture
+            .manager
+            .get_task(&ids[0])
+            .unwrap()
+            .temp_files
+            .is_empty());
+    }
+
+    #[test]
+    fn failure_releases_slots() {
+        let fixture = Fixture::new("echo 'ERROR: fake failure' >&2\nexit 1");
+        let ids = fixture.start(3);
+
+        wait_until("downloads to fail", || {
+            ids.iter()
+                .all(|id| fixture.status(id) == DownloadStatus::Failed)
+        });
+        wait_until("slots to be released", || fixture.active() == 0);
+        assert_eq!(
+            fixture.manager.get_task(&ids[0]).unwrap().error.as_deref(),
+            Some("fake failure")
+        );
+    }
 
-            // Clean up the .info.json file after download completes
-            let _ = std::fs::remove_file(&info_json_path);
+    #[test]
+    fn spawn_failure_releases_slots() {
+        let fixture = Fixture::new("exit 0");
+        std::fs::remove_file(fixture.manager.ytdlp.get_exe_path()).unwrap();
+        let ids = fixture.start(3);
 
-            // Release download slot and notify waiting tasks
-            active_downloads.fetch_sub(1, Ordering::SeqCst);
-            notify.notify_waiters();
+        wait_until("downloads to fail", || {
+            ids.iter()
+                .all(|id| fixture.status(id) == DownloadStatus::Failed)
         });
+        wait_until("slots to be released", || fixture.active() == 0);
     }
 }
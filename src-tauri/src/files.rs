@@ -0,0 +1,26 @@
+//! Opening downloaded files and revealing them in the system file manager
+//!
+//! Both go through tauri-plugin-opener, which passes the path to the OS
+//! without a shell, so characters from video titles can't be interpreted.
+
+use std::path::Path;
+
+/// Open a file with its default application
+pub fn open_path(path: &Path) -> Result<(), String> {
+    if !path.exists() {
+        return Err("File no longer exists".to_string());
+    }
+
+    tauri_plugin_opener::open_path(path, None::<&str>)
+        .map_err(|e| format!("Failed to open file: {}", e))
+}
+
+/// Show a file selected in the system file manager
+pub fn reveal_path(path: &Path) -> Result<(), String> {
+    if !path.exists() {
+        return Err("File no longer exists".to_string());
+    }
+
+    tauri_plugin_opener::reveal_item_in_dir(path)
+        .map_err(|e| format!("Failed to reveal file: {}", e))
+}
@@ -0,0 +1,184 @@
+//! yt-dlp format selection
+//!
+//! Builds the `-f` / `-S` / `--merge-output-format` arguments for a task from
+//! its explicit format choice or, failing that, its resolution and the codec
+//! preferences in AppSettings. Audio-only tasks are extracted and transcoded
+//! with ffmpeg.
+
+use crate::models::{AppSettings, AudioOptions, DownloadTask};
+
+/// Audio formats that ffmpeg can extract to
+const AUDIO_FORMATS: &[&str] = &["mp3", "opus", "flac", "m4a"];
+
+/// Height limit for a resolution name, None for "best"
+fn max_height(resolution: &str) -> Option<u32> {
+    match resolution {
+        "best" => None,
+        "2160p" | "4K" => Some(2160),
+        "1440p" | "2K" => Some(1440),
+        "1080p" => Some(1080),
+        "720p" => Some(720),
+        "480p" => Some(480),
+        "360p" => Some(360),
+        _ => Some(1080),
+    }
+}
+
+/// Format sort string (`-S`) built from the settings' preferences
+fn sort_string(settings: &AppSettings, height: Option<u32>) -> String {
+    let mut fields = Vec::new();
+
+    if let Some(height) = height {
+        fields.push(format!("res:{}", height));
+    }
+    if settings.max_fps > 0 {
+        fields.push(format!("fps:{}", settings.max_fps));
+    }
+    let hdr = if settings.prefer_hdr {
+        "hdr"
+    } else {
+        "hdr:sdr"
+    };
+    fields.push(hdr.to_string());
+
+    match settings.preferred_video_codec.as_str() {
+        "av1" => fields.push("vcodec:av01".to_string()),
+        "vp9" => fields.push("vcodec:vp9".to_string()),
+        "h264" => fields.push("vcodec:h264".to_string()),
+        _ => {}
+    }
+    match settings.preferred_audio_codec.as_str() {
+        "opus" => fields.push("acodec:opus".to_string()),
+        "aac" => fields.push("acodec:aac".to_string()),
+        _ => {}
+    }
+    match settings.preferred_container.as_str() {
+        "mp4" => fields.push("ext:mp4:m4a".to_string()),
+        "webm" => fields.push("ext:webm:webm".to_string()),
+        _ => {}
+    }
+
+    fields.join(",")
+}
+
+/// Format sort string for audio extraction: a source codec that matches the
+/// target comes first so it is copied rather than re-encoded. Video and
+/// container preferences don't apply to an audio-only download.
+fn audio_sort_string(audio: &AudioOptions, settings: &AppSettings) -> Option<String> {
+    let codec = match audio.format.as_str() {
+        "opus" => "opus",
+        "m4a" => "aac",
+        // mp3 and flac are always encoded, fall back to the preferred codec
+        _ => match settings.preferred_audio_codec.as_str() {
+            "opus" => "opus",
+            "aac" => "aac",
+            _ => return None,
+        },
+    };
+    Some(format!("acodec:{}", codec))
+}
+
+/// Build the format-related yt-dlp arguments for a task
+pub fn format_args(task: &DownloadTask, settings: &AppSettings) -> Vec<String> {
+    let options = &task.options;
+    let mut args = Vec::new();
+
+    let explicit = match (&options.video_format_id, &options.audio_format_id) {
+        (Some(video), Some(audio)) => Some(format!("{}+{}", video, audio)),
+        (Some(video), None) => Some(video.clone()),
+        (None, Some(audio)) => Some(audio.clone()),
+        (None, None) => None,
+    };
+
+    if let Some(selector) = options.format.clone().or(explicit) {
+        args.push("-f".to_string());
+        args.push(selector);
+    } else if let Some(audio) = audio_options(task, settings) {
+        args.push("-f".to_string());
+        args.push("ba/b".to_string());
+        if let Some(sort) = audio_sort_string(&audio, settings) {
+            args.push("-S".to_string());
+            args.push(sort);
+        }
+    } else {
+        let height = max_height(&task.resolution);
+        let selector = match height {
+            Some(h) => format!("bv*[height<={h}]+ba/b[height<={h}]/bv*+ba/b", h = h),
+            None => "bv*+ba/b".to_string(),
+        };
+        args.push("-f".to_string());
+        args.push(selector);
+        args.push("-S".to_string());
+        args.push(sort_string(settings, height));
+    }
+
+    if let Some(audio) = audio_options(task, settings) {
+        args.extend(audio_args(&audio));
+    } else if settings.preferred_container != "any" {
+        args.push("--merge-output-format".to_string());
+        args.push(settings.preferred_container.clone());
+    }
+
+    args
+}
+
+/// Effective audio options if the task extracts audio only
+pub fn audio_options(task: &DownloadTask, settings: &AppSettings) -> Option<AudioOptions> {
+    match &task.options.audio {
+        Some(audio) => Some(audio.clone()),
+        None if task.resolution == "audio" => Some(settings.default_audio.clone()),
+        None => None,
+    }
+}
+
+/// yt-dlp arguments that extract the audio stream. Tagging is left to
+/// `metadata::metadata_args`, which follows the audio options for audio tasks.
+fn audio_args(audio: &AudioOptions) -> Vec<String> {
+    let mut args = vec![
+        "-x".to_string(),
+        "--audio-format".to_string(),
+        audio.format.clone(),
+    ];
+
+    if audio.format != "flac" && !audio.bitrate.is_empty() {
+        args.push("--audio-quality".to_string());
+        args.push(audio.bitrate.clone());
+    }
+    args
+}
+
+/// Check that audio options name a supported format and a sane bitrate
+pub fn validate_audio(audio: &AudioOptions) -> Result<(), String> {
+    if !AUDIO_FORMATS.contains(&audio.format.as_str()) {
+        return Err(format!("Unsupported audio format: {}", audio.format));
+    }
+
+    let digits = audio.bitrate.trim_end_matches(['K', 'k']);
+    if !audio.bitrate.is_empty() && (digits.is_empty() || digits.parse::<u32>().is_err()) {
+        return Err(format!("Invalid audio bitrate: {}", audio.bitrate));
+    }
+
+    Ok(())
+}
+
+/// Check explicit format IDs against the probed format list, if there is one
+pub fn validate_format_ids(task: &DownloadTask) -> Result<(), String> {
+    let formats = match &task.video_info {
+        Some(info) if !info.formats.is_empty() => &info.formats,
+        _ => return Ok(()),
+    };
+
+    for format_id in [&task.options.video_format_id, &task.options.audio_format_id]
+        .into_iter()
+        .flatten()
+    {
+        if !formats.iter().any(|f| &f.format_id == format_id) {
+            return Err(format!(
+                "Format {} is not available for this video",
+                format_id
+            ));
+        }
+    }
+
+    Ok(())
+}
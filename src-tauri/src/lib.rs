@@ -1,10 +1,26 @@
+mod archive;
 mod aria2;
 mod auth;
+mod cleanup;
+mod clip;
 mod cookies;
 mod download;
 mod ffmpeg;
+mod files;
+mod format;
+mod live;
+mod metadata;
 mod models;
+mod playlist;
+mod process;
+mod progress;
+mod queue;
+mod schedule;
 mod settings;
+mod sponsorblock;
+mod subscriptions;
+mod subtitles;
+mod template;
 mod ytdlp;
 
 use aria2::{Aria2Manager, Aria2Status};
@@ -12,10 +28,14 @@ use auth::AuthManager;
 use cookies::convert_cookies_to_netscape;
 use download::DownloadManager;
 use ffmpeg::{FFmpegManager, FFmpegStatus};
-use models::{AppSettings, CookiesValidationResult, DownloadTask, LoginStatus, YtDlpStatus};
+use models::{
+    AppSettings, CookiesValidationResult, DownloadTask, LoginStatus, PlaylistEntry, PlaylistFilter,
+    Subscription, SubscriptionSyncedEvent, TaskOptions, VideoInfo, YtDlpStatus,
+};
 use settings::SettingsManager;
 use std::path::PathBuf;
 use std::sync::Arc;
+use subscriptions::SubscriptionManager;
 use tauri::{AppHandle, Emitter, Manager, State, Url, WebviewWindow};
 
 pub struct AppState {
@@ -25,6 +45,7 @@ pub struct AppState {
     pub aria2: Arc<Aria2Manager>,
     pub download: DownloadManager,
     pub auth: Arc<AuthManager>,
+    pub subscriptions: SubscriptionManager,
 }
 
 // ==================== Settings Commands ====================
@@ -35,11 +56,37 @@ fn get_settings(state: State<AppState>) -> AppSettings {
 }
 
 #[tauri::command]
-fn save_settings(state: State<AppState>, settings: AppSettings) -> Result<(), String> {
+fn save_settings(
+    state: State<AppState>,
+    app_handle: AppHandle,
+    settings: AppSettings,
+) -> Result<(), String> {
+    format::validate_audio(&settings.default_audio)?;
+    subtitles::validate_subtitles(&settings.default_subtitles)?;
+    template::validate_template(&settings.output_template)?;
+    sponsorblock::validate_sponsorblock(&settings.default_sponsorblock)?;
+    sponsorblock::validate_api(&settings.sponsorblock_api)?;
+    metadata::validate_metadata(&settings.default_metadata)?;
+    schedule::validate_schedule(&settings.schedule)?;
+
+    // Running downloads got their speed share from the old limit and slot count
+    let previous = state.settings.get();
+    let rate_limit_changed = previous.rate_limit_kib != settings.rate_limit_kib
+        || (settings.rate_limit_kib > 0
+            && previous.default_concurrent != settings.default_concurrent);
+
     // Update download manager's concurrent limit in real-time
     state
         .download
         .set_max_concurrent(settings.default_concurrent);
+    state.download.set_rate_limit(settings.rate_limit_kib);
+    state.download.set_schedule(settings.schedule.clone());
+    if rate_limit_changed {
+        let cookies_path = cookies::get_cookies_file_path(&state.settings.get_app_data_dir());
+        state
+            .download
+            .restart_downloads(&settings, &app_handle, &cookies_path);
+    }
     state.settings.save(settings)
 }
 
@@ -86,9 +133,31 @@ async fn download_aria2(app_handle: AppHandle, state: State<'_, AppState>) -> Re
 
 // ==================== Download Commands ====================
 
+/// Create a task for a URL, or for an entry returned by expand_playlist
+#[tauri::command]
+fn create_download_task(
+    state: State<AppState>,
+    url: String,
+    resolution: String,
+    options: Option<TaskOptions>,
+    playlist_entry: Option<PlaylistEntry>,
+) -> Result<DownloadTask, String> {
+    let options = options.unwrap_or_default();
+    match playlist_entry {
+        Some(entry) => state
+            .download
+            .create_task_from_entry(&entry, resolution, options),
+        None => state.download.create_task(url, resolution, options),
+    }
+}
+
 #[tauri::command]
-fn create_download_task(state: State<AppState>, url: String, resolution: String) -> DownloadTask {
-    state.download.create_task(url, resolution)
+fn set_task_options(
+    state: State<AppState>,
+    task_id: String,
+    options: TaskOptions,
+) -> Result<(), String> {
+    state.download.set_task_options(&task_id, options)
 }
 
 #[tauri::command]
@@ -102,7 +171,7 @@ fn start_download(
 
     state
         .download
-        .start_download(task_id, settings.download_dir, app_handle, cookies_path);
+        .start_download(task_id, settings, app_handle, cookies_path);
     Ok(())
 }
 
@@ -118,7 +187,8 @@ fn get_all_tasks(state: State<AppState>) -> Vec<DownloadTask> {
 
 #[tauri::command]
 fn remove_task(state: State<AppState>, task_id: String) {
-    state.download.remove_task(&task_id);
+    let keep_partials = state.settings.get().keep_partials;
+    state.download.remove_task(&task_id, keep_partials);
 }
 
 #[tauri::command]
@@ -127,9 +197,39 @@ fn clear_completed_tasks(state: State<AppState>) {
 }
 
 #[tauri::command]
-fn expand_playlist(state: State<AppState>, url: String) -> Result<Vec<String>, String> {
+fn expand_playlist(
+    state: State<AppState>,
+    url: String,
+    filter: Option<PlaylistFilter>,
+) -> Result<Vec<PlaylistEntry>, String> {
+    let cookies_path = cookies::get_cookies_file_path(&state.settings.get_app_data_dir());
+    state
+        .download
+        .expand_playlist(&url, &cookies_path, &filter.unwrap_or_default())
+}
+
+/// Fetch formats and metadata before queuing.
+/// If task_id is given, the result is cached on that task.
+#[tauri::command]
+async fn probe_url(
+    state: State<'_, AppState>,
+    url: String,
+    task_id: Option<String>,
+) -> Result<VideoInfo, String> {
     let cookies_path = cookies::get_cookies_file_path(&state.settings.get_app_data_dir());
-    state.download.expand_playlist(&url, &cookies_path)
+    let download = state.download.clone();
+
+    let info = tokio::task::spawn_blocking(move || download.probe_url(&url, &cookies_path))
+        .await
+        .map_err(|e| format!("Probe task failed: {}", e))??;
+
+    if let Some(task_id) = task_id {
+        state
+            .download
+            .update_task_video_info(&task_id, info.clone());
+    }
+
+    Ok(info)
 }
 
 #[tauri::command]
@@ -149,7 +249,53 @@ fn resume_download(
     // Resume by re-starting the download (yt-dlp will continue from .part file)
     state
         .download
-        .start_download(task_id, settings.download_dir, app_handle, cookies_path);
+        .start_download(task_id, settings, app_handle, cookies_path);
+    Ok(())
+}
+
+/// Cancel a download and remove its temporary files. `keep_partials`
+/// overrides the setting of the same name.
+#[tauri::command]
+fn cancel_download(state: State<AppState>, task_id: String, keep_partials: Option<bool>) {
+    let keep_partials = keep_partials.unwrap_or_else(|| state.settings.get().keep_partials);
+    state.download.cancel_download(&task_id, keep_partials);
+}
+
+/// Stop a live recording and keep what was recorded so far
+#[tauri::command]
+fn stop_recording(state: State<AppState>, task_id: String) -> Result<(), String> {
+    state.download.stop_recording(&task_id)
+}
+
+#[tauri::command]
+fn retry_download(
+    app_handle: AppHandle,
+    state: State<AppState>,
+    task_id: String,
+) -> Result<(), String> {
+    let settings = state.settings.get();
+    let cookies_path = cookies::get_cookies_file_path(&state.settings.get_app_data_dir());
+
+    state.download.retry_download(&task_id)?;
+    state
+        .download
+        .start_download(task_id, settings, app_handle, cookies_path);
+    Ok(())
+}
+
+/// Start a task right away, even if all download slots are busy
+#[tauri::command]
+fn start_download_now(
+    app_handle: AppHandle,
+    state: State<AppState>,
+    task_id: String,
+) -> Result<(), String> {
+    let settings = state.settings.get();
+    let cookies_path = cookies::get_cookies_file_path(&state.settings.get_app_data_dir());
+
+    state
+        .download
+        .start_now(task_id, settings, app_handle, cookies_path);
     Ok(())
 }
 
@@ -185,6 +331,156 @@ fn open_download_folder(state: State<AppState>) -> Result<(), String> {
     }
 }
 
+/// Open a task's downloaded file with its default application
+#[tauri::command]
+fn open_task_file(state: State<AppState>, task_id: String) -> Result<(), String> {
+    let path = task_output_path(&state, &task_id)?;
+    files::open_path(&path)
+}
+
+/// Show a task's downloaded file in the system file manager
+#[tauri::command]
+fn reveal_task_file(state: State<AppState>, task_id: String) -> Result<(), String> {
+    let path = task_output_path(&state, &task_id)?;
+    files::reveal_path(&path)
+}
+
+fn task_output_path(state: &State<AppState>, task_id: &str) -> Result<PathBuf, String> {
+    let task = state
+        .download
+        .get_task(task_id)
+        .ok_or_else(|| "Task not found".to_string())?;
+    task.output_path
+        .ok_or_else(|| "Task has no output file yet".to_string())
+}
+
+/// Find temporary files left in the download folder by tasks that are gone
+#[tauri::command]
+fn find_orphans(state: State<AppState>) -> Vec<String> {
+    let download_dir = state.settings.get().download_dir;
+    state
+        .download
+        .find_orphans(&download_dir)
+        .into_iter()
+        .map(|path| path.to_string_lossy().to_string())
+        .collect()
+}
+
+/// Delete leftover files the user confirmed. Returns the deleted paths.
+#[tauri::command]
+fn delete_orphans(state: State<AppState>, paths: Vec<String>) -> Vec<String> {
+    let download_dir = state.settings.get().download_dir;
+    state
+        .download
+        .delete_orphans(
+            &download_dir,
+            paths.into_iter().map(PathBuf::from).collect(),
+        )
+        .into_iter()
+        .map(|path| path.to_string_lossy().to_string())
+        .collect()
+}
+
+// ==================== Queue Order Commands ====================
+
+#[tauri::command]
+fn move_task_to_top(state: State<AppState>, task_id: String) -> Result<(), String> {
+    state.download.move_task_to_top(&task_id)
+}
+
+#[tauri::command]
+fn move_task_to_bottom(state: State<AppState>, task_id: String) -> Result<(), String> {
+    state.download.move_task_to_bottom(&task_id)
+}
+
+#[tauri::command]
+fn move_task_before(
+    state: State<AppState>,
+    task_id: String,
+    before_id: String,
+) -> Result<(), String> {
+    state.download.move_task_before(&task_id, &before_id)
+}
+
+#[tauri::command]
+fn set_task_priority(state: State<AppState>, task_id: String, priority: i32) -> Result<(), String> {
+    state.download.set_task_priority(&task_id, priority)
+}
+
+// ==================== Archive Commands ====================
+
+#[tauri::command]
+fn get_archive_entries(state: State<AppState>) -> Vec<String> {
+    state.download.archive().entries()
+}
+
+/// Merge another yt-dlp archive file, returns the number of new entries
+#[tauri::command]
+fn import_archive(state: State<AppState>, path: String) -> Result<usize, String> {
+    state.download.archive().import(std::path::Path::new(&path))
+}
+
+#[tauri::command]
+fn export_archive(state: State<AppState>, path: String) -> Result<(), String> {
+    state.download.archive().export(std::path::Path::new(&path))
+}
+
+/// Remove entries so they can be downloaded again, returns the number removed
+#[tauri::command]
+fn prune_archive(state: State<AppState>, entries: Vec<String>) -> Result<usize, String> {
+    state.download.archive().prune(&entries)
+}
+
+// ==================== Subscription Commands ====================
+
+#[tauri::command]
+fn get_subscriptions(state: State<AppState>) -> Vec<Subscription> {
+    state.subscriptions.get_all()
+}
+
+#[tauri::command]
+fn add_subscription(
+    state: State<AppState>,
+    url: String,
+    name: Option<String>,
+    resolution: String,
+    output_template: Option<String>,
+    check_interval_mins: u64,
+    backfill: bool,
+) -> Result<Subscription, String> {
+    state.subscriptions.add(
+        url,
+        name,
+        resolution,
+        output_template,
+        check_interval_mins,
+        backfill,
+    )
+}
+
+#[tauri::command]
+fn update_subscription(state: State<AppState>, subscription: Subscription) -> Result<(), String> {
+    state.subscriptions.update(subscription)
+}
+
+#[tauri::command]
+fn remove_subscription(state: State<AppState>, subscription_id: String) -> Result<(), String> {
+    state.subscriptions.remove(&subscription_id)
+}
+
+/// Check a subscription now instead of waiting for its interval
+#[tauri::command]
+async fn sync_subscription(
+    app_handle: AppHandle,
+    subscription_id: String,
+) -> Result<SubscriptionSyncedEvent, String> {
+    tokio::task::spawn_blocking(move || {
+        subscriptions::sync_subscription(&app_handle, &subscription_id)
+    })
+    .await
+    .map_err(|e| format!("Sync task failed: {}", e))
+}
+
 // ==================== Auth Commands ====================
 
 #[tauri::command]
@@ -516,12 +812,24 @@ pub fn run() {
             let ffmpeg = Arc::new(FFmpegManager::new(app_data_dir.clone()));
             let aria2 = Arc::new(Aria2Manager::new(app_data_dir.clone()));
             let auth = Arc::new(AuthManager::new(app_data_dir.clone()));
+            let subscriptions = SubscriptionManager::new(app_data_dir.clone());
             let default_concurrent = settings.get().default_concurrent;
+            let rate_limit_kib = settings.get().rate_limit_kib;
+            let schedule = settings.get().schedule;
             let download = DownloadManager::new(
+                app_data_dir.clone(),
                 ytdlp.clone(),
                 ffmpeg.clone(),
                 aria2.clone(),
                 default_concurrent,
+                rate_limit_kib,
+                schedule,
+            );
+            download.spawn_scheduler();
+            download.requeue_pending(
+                &settings.get(),
+                app.handle(),
+                &cookies::get_cookies_file_path(&app_data_dir),
             );
 
             app.manage(AppState {
@@ -531,8 +839,12 @@ pub fn run() {
                 aria2,
                 download,
                 auth,
+                subscriptions,
             });
 
+            subscriptions::spawn_sync_loop(app.handle().clone());
+            schedule::spawn_schedule_loop(app.handle().clone());
+
             Ok(())
         })
         .invoke_handler(tauri::generate_handler![
@@ -551,15 +863,41 @@ pub fn run() {
             download_aria2,
             // Downloads
             create_download_task,
+            set_task_options,
             start_download,
             get_download_task,
             get_all_tasks,
             remove_task,
             clear_completed_tasks,
             expand_playlist,
+            probe_url,
             pause_download,
             resume_download,
+            cancel_download,
+            stop_recording,
+            retry_download,
+            start_download_now,
             open_download_folder,
+            open_task_file,
+            reveal_task_file,
+            find_orphans,
+            delete_orphans,
+            // Queue order
+            move_task_to_top,
+            move_task_to_bottom,
+            move_task_before,
+            set_task_priority,
+            // Archive
+            get_archive_entries,
+            import_archive,
+            export_archive,
+            prune_archive,
+            // Subscriptions
+            get_subscriptions,
+            add_subscription,
+            update_subscription,
+            remove_subscription,
+            sync_subscription,
             // Auth
             get_login_status,
             open_login_window,
@@ -573,6 +911,14 @@ pub fn run() {
             validate_and_cleanup_cookies,
             clear_all_data,
         ])
-        .run(tauri::generate_context!())
-        .expect("error while running tauri application");
+        .build(tauri::generate_context!())
+        .expect("error while building tauri application")
+        .run(|app_handle, event| {
+            // Queue saves are deferred, write the last changes before exiting
+            if let tauri::RunEvent::Exit = event {
+                if let Some(state) = app_handle.try_state::<AppState>() {
+                    state.download.flush_queue();
+                }
+            }
+        });
 }
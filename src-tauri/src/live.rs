@@ -0,0 +1,27 @@
+//! Live stream and premiere recording
+//!
+//! Recordings are written without a `.part` file and as MPEG-TS, so the
+//! output is playable even if the recording is interrupted. Stopping a
+//! recording interrupts yt-dlp, which asks ffmpeg to finish the file.
+
+use crate::models::LiveOptions;
+
+/// yt-dlp arguments for recording a live stream
+pub fn live_args(live: &LiveOptions) -> Vec<String> {
+    let mut args = vec![
+        if live.from_start {
+            "--live-from-start".to_string()
+        } else {
+            "--no-live-from-start".to_string()
+        },
+        "--no-part".to_string(),
+        "--hls-use-mpegts".to_string(),
+    ];
+
+    if live.wait_for_video {
+        args.push("--wait-for-video".to_string());
+        args.push(live.wait_retry_secs.max(1).to_string());
+    }
+
+    args
+}
@@ -0,0 +1,81 @@
+//! Metadata, chapter and thumbnail embedding, and sidecar files
+//!
+//! Embedding is done by yt-dlp's postprocessors with the bundled ffmpeg, so
+//! it is skipped when ffmpeg isn't installed. Sidecars are written next to
+//! the output file with the same name.
+
+use crate::format;
+use crate::models::{AppSettings, DownloadTask, MetadataOptions};
+
+/// Containers that can hold an embedded thumbnail
+const THUMBNAIL_CONTAINERS: &[&str] = &["mp4", "m4a", "mkv", "mka", "mp3", "opus", "flac"];
+
+/// Effective metadata options for a task. Audio-only tasks take their tag
+/// and cover art choices from their audio options.
+pub fn metadata_options(task: &DownloadTask, settings: &AppSettings) -> MetadataOptions {
+    let mut options = task
+        .options
+        .metadata
+        .clone()
+        .unwrap_or_else(|| settings.default_metadata.clone());
+    if let Some(audio) = format::audio_options(task, settings) {
+        options.embed_metadata = audio.embed_metadata;
+        options.embed_thumbnail = audio.embed_thumbnail;
+    }
+    options
+}
+
+/// Output container of a task, if it is known before downloading
+pub fn output_container(task: &DownloadTask, settings: &AppSettings) -> Option<String> {
+    match format::audio_options(task, settings) {
+        Some(audio) => Some(audio.format),
+        None if settings.preferred_container != "any" => Some(settings.preferred_container.clone()),
+        None => None,
+    }
+}
+
+/// yt-dlp arguments for embedding metadata and writing sidecar files
+pub fn metadata_args(
+    options: &MetadataOptions,
+    container: Option<&str>,
+    has_ffmpeg: bool,
+) -> Vec<String> {
+    let mut args = Vec::new();
+
+    if has_ffmpeg {
+        if options.embed_metadata {
+            args.push("--embed-metadata".to_string());
+        }
+        if options.embed_chapters {
+            args.push("--embed-chapters".to_string());
+        }
+        // webm can't carry a cover image, and an unknown container might be webm
+        let can_embed_thumbnail = container
+            .map(|c| THUMBNAIL_CONTAINERS.contains(&c))
+            .unwrap_or(false);
+        if options.embed_thumbnail && can_embed_thumbnail {
+            args.push("--embed-thumbnail".to_string());
+        }
+        if options.embed_thumbnail || options.write_thumbnail {
+            args.push("--convert-thumbnails".to_string());
+            args.push(options.thumbnail_format.clone());
+        }
+    }
+
+    if options.write_description {
+        args.push("--write-description".to_string());
+    }
+    if options.write_thumbnail {
+        args.push("--write-thumbnail".to_string());
+    }
+
+    args
+}
+
+/// Check that the thumbnail format is one ffmpeg converts to
+pub fn validate_metadata(options: &MetadataOptions) -> Result<(), String> {
+    match options.thumbnail_format.as_str() {
+        "jpg" | "png" => Ok(()),
+        other => Err(format!("Unsupported thumbnail format: {}", other)),
+    }
+}
@@ -1,4 +1,5 @@
 use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
 use std::path::PathBuf;
 
 /// Application settings
@@ -28,6 +29,82 @@ pub struct AppSettings {
     /// User avatar URL (persisted across restarts)
     #[serde(default)]
     pub avatar_url: Option<String>,
+    /// Automatically retry downloads that fail with a transient error
+    #[serde(default)]
+    pub auto_retry: bool,
+    /// Maximum automatic retries per task
+    #[serde(default = "default_max_retries")]
+    pub max_retries: u32,
+    /// Delay before the first automatic retry, doubled on each attempt
+    #[serde(default = "default_retry_backoff_secs")]
+    pub retry_backoff_secs: u64,
+    /// Preferred container: "mp4", "mkv", "webm" or "any"
+    #[serde(default = "default_container")]
+    pub preferred_container: String,
+    /// Preferred video codec: "av1", "vp9", "h264" or "any"
+    #[serde(default = "default_codec")]
+    pub preferred_video_codec: String,
+    /// Preferred audio codec: "opus", "aac" or "any"
+    #[serde(default = "default_codec")]
+    pub preferred_audio_codec: String,
+    /// Prefer HDR streams over SDR when both exist
+    #[serde(default)]
+    pub prefer_hdr: bool,
+    /// Highest frame rate to prefer (0 = no limit)
+    #[serde(default)]
+    pub max_fps: u32,
+    /// Audio extraction defaults for the "audio" resolution
+    #[serde(default)]
+    pub default_audio: AudioOptions,
+    /// Subtitle defaults for new tasks
+    #[serde(default)]
+    pub default_subtitles: SubtitleOptions,
+    /// Output filename template relative to download_dir, e.g. "{uploader}/{title}.{ext}"
+    #[serde(default = "default_output_template")]
+    pub output_template: String,
+    /// SponsorBlock defaults for new tasks
+    #[serde(default)]
+    pub default_sponsorblock: SponsorBlockOptions,
+    /// SponsorBlock API endpoint
+    #[serde(default = "default_sponsorblock_api")]
+    pub sponsorblock_api: String,
+    /// Metadata embedding and sidecar defaults for new tasks
+    #[serde(default)]
+    pub default_metadata: MetadataOptions,
+    /// Total download speed limit in KiB/s (0 = no limit). Each download gets
+    /// an equal per-slot share, so idle slots leave part of it unused.
+    #[serde(default)]
+    pub rate_limit_kib: u64,
+    /// Daily window in which downloads may run
+    #[serde(default)]
+    pub schedule: ScheduleOptions,
+    /// Keep .part files of cancelled downloads so they can be resumed later
+    #[serde(default)]
+    pub keep_partials: bool,
+}
+
+fn default_max_retries() -> u32 {
+    3
+}
+
+fn default_retry_backoff_secs() -> u64 {
+    5
+}
+
+fn default_output_template() -> String {
+    crate::template::DEFAULT_TEMPLATE.to_string()
+}
+
+fn default_sponsorblock_api() -> String {
+    crate::sponsorblock::DEFAULT_API.to_string()
+}
+
+fn default_container() -> String {
+    "mp4".to_string()
+}
+
+fn default_codec() -> String {
+    "any".to_string()
 }
 
 impl Default for AppSettings {
@@ -46,10 +123,141 @@ impl Default for AppSettings {
             cookies_browser: "chrome".to_string(),
             cookies_profile: "Default".to_string(),
             avatar_url: None,
+            auto_retry: false,
+            max_retries: default_max_retries(),
+            retry_backoff_secs: default_retry_backoff_secs(),
+            preferred_container: default_container(),
+            preferred_video_codec: default_codec(),
+            preferred_audio_codec: default_codec(),
+            prefer_hdr: false,
+            max_fps: 0,
+            default_audio: AudioOptions::default(),
+            default_subtitles: SubtitleOptions::default(),
+            output_template: default_output_template(),
+            default_sponsorblock: SponsorBlockOptions::default(),
+            sponsorblock_api: default_sponsorblock_api(),
+            default_metadata: MetadataOptions::default(),
+            rate_limit_kib: 0,
+            schedule: ScheduleOptions::default(),
+            keep_partials: false,
+        }
+    }
+}
+
+/// Audio extraction and transcoding options
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AudioOptions {
+    /// Target format: "mp3", "opus", "flac" or "m4a"
+    pub format: String,
+    /// Target bitrate (e.g. "192K"), ignored for flac
+    pub bitrate: String,
+    /// Write title and artist tags
+    pub embed_metadata: bool,
+    /// Embed the video thumbnail as cover art
+    pub embed_thumbnail: bool,
+}
+
+impl Default for AudioOptions {
+    fn default() -> Self {
+        Self {
+            format: "mp3".to_string(),
+            bitrate: "192K".to_string(),
+            embed_metadata: true,
+            embed_thumbnail: true,
+        }
+    }
+}
+
+/// Tags embedded into the output file and sidecar files written next to it
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MetadataOptions {
+    /// Title, uploader, upload date and description tags
+    pub embed_metadata: bool,
+    pub embed_chapters: bool,
+    /// Embed the thumbnail as cover art where the container supports it
+    pub embed_thumbnail: bool,
+    /// Thumbnail conversion target: "jpg" or "png"
+    pub thumbnail_format: String,
+    /// Keep the .info.json next to the output file
+    pub write_info_json: bool,
+    pub write_description: bool,
+    pub write_thumbnail: bool,
+}
+
+impl Default for MetadataOptions {
+    fn default() -> Self {
+        Self {
+            embed_metadata: true,
+            embed_chapters: true,
+            embed_thumbnail: true,
+            thumbnail_format: "jpg".to_string(),
+            write_info_json: false,
+            write_description: false,
+            write_thumbnail: false,
         }
     }
 }
 
+/// SponsorBlock categories to mark as chapters or cut out
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SponsorBlockOptions {
+    /// Categories to mark as chapters, e.g. ["sponsor", "intro"]
+    pub mark: Vec<String>,
+    /// Categories to remove from the file
+    pub remove: Vec<String>,
+}
+
+/// A SponsorBlock segment that was cut from a download
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SponsorSegment {
+    pub category: String,
+    pub start_time: f64,
+    pub end_time: f64,
+}
+
+/// Subtitle download options
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SubtitleOptions {
+    pub enabled: bool,
+    /// Language codes or yt-dlp patterns (e.g. "en", "zh-Hans", "en.*"), empty for all
+    pub languages: Vec<String>,
+    /// Download subtitles uploaded by the creator
+    pub manual: bool,
+    /// Download auto-generated captions
+    pub auto_generated: bool,
+    /// Output format: "srt", "vtt" or "ass"
+    pub format: String,
+    /// Embed into the video instead of writing sidecar files
+    pub embed: bool,
+}
+
+impl Default for SubtitleOptions {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            languages: vec!["en".to_string()],
+            manual: true,
+            auto_generated: false,
+            format: "srt".to_string(),
+            embed: true,
+        }
+    }
+}
+
+/// A subtitle track reported by the probe
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SubtitleTrack {
+    pub language: String,
+    pub name: Option<String>,
+    /// Available formats, e.g. ["vtt", "srv3", "json3"]
+    pub formats: Vec<String>,
+    pub auto_generated: bool,
+}
+
 /// Video Information
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct VideoInfo {
@@ -64,6 +272,18 @@ pub struct VideoInfo {
     pub formats: Vec<FormatInfo>,
     pub playlist_index: Option<u32>,
     pub playlist_count: Option<u32>,
+    #[serde(default)]
+    pub subtitles: Vec<SubtitleTrack>,
+    #[serde(default)]
+    pub chapters: Vec<Chapter>,
+}
+
+/// A chapter marker from the video's metadata
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Chapter {
+    pub title: String,
+    pub start_time: f64,
+    pub end_time: f64,
 }
 
 /// Video Format Information
@@ -77,6 +297,126 @@ pub struct FormatInfo {
     pub filesize_approx: Option<u64>,
     pub vcodec: Option<String>,
     pub acodec: Option<String>,
+    #[serde(default)]
+    pub width: Option<u32>,
+    #[serde(default)]
+    pub height: Option<u32>,
+    #[serde(default)]
+    pub fps: Option<f64>,
+    /// Total bitrate in KBit/s
+    #[serde(default)]
+    pub tbr: Option<f64>,
+    /// "SDR", "HDR10", "HLG", ...
+    #[serde(default)]
+    pub dynamic_range: Option<String>,
+    #[serde(default)]
+    pub audio_channels: Option<u32>,
+    #[serde(default)]
+    pub language: Option<String>,
+    /// Transport protocol, e.g. "https" or "m3u8_native"
+    #[serde(default)]
+    pub protocol: Option<String>,
+}
+
+/// Per-task download options chosen when the task is created
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TaskOptions {
+    /// Raw yt-dlp format selector, overrides everything else
+    pub format: Option<String>,
+    /// Video format_id picked from the probed FormatInfo list
+    pub video_format_id: Option<String>,
+    /// Audio format_id picked from the probed FormatInfo list
+    pub audio_format_id: Option<String>,
+    /// Extract audio only, overriding the default audio options
+    pub audio: Option<AudioOptions>,
+    /// Subtitle options, overriding the default subtitle options
+    pub subtitles: Option<SubtitleOptions>,
+    /// Output filename template, overriding the one in settings
+    pub output_template: Option<String>,
+    /// Record a live stream or premiere instead of a regular download
+    pub live: Option<LiveOptions>,
+    /// Download only some time ranges or chapters
+    pub clip: Option<ClipOptions>,
+    /// Split the finished file into one file per chapter
+    pub split_chapters: bool,
+    /// Re-encode around the chapter starts for exact splits (slower)
+    pub precise_chapter_cuts: bool,
+    /// SponsorBlock options, overriding the default SponsorBlock options
+    pub sponsorblock: Option<SponsorBlockOptions>,
+    /// Metadata options, overriding the default metadata options
+    pub metadata: Option<MetadataOptions>,
+    /// Download speed limit for this task in KiB/s, applied on top of the global limit
+    pub rate_limit_kib: Option<u64>,
+    /// Keep the task queued until this time (Unix timestamp)
+    pub start_after: Option<u64>,
+    /// Download even if the video is in the archive, and don't record it there
+    pub ignore_archive: bool,
+}
+
+/// Daily download window in local time, e.g. 01:00 to 07:00.
+/// A window whose end is before its start wraps past midnight.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ScheduleOptions {
+    pub enabled: bool,
+    /// Window start, "HH:MM"
+    pub start: String,
+    /// Window end, "HH:MM"
+    pub end: String,
+    /// "allow" to only download inside the window, "block" to pause downloads inside it
+    pub mode: String,
+}
+
+impl Default for ScheduleOptions {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            start: "01:00".to_string(),
+            end: "07:00".to_string(),
+            mode: "allow".to_string(),
+        }
+    }
+}
+
+/// Sections of a video to download instead of the whole video
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ClipOptions {
+    pub sections: Vec<TimeRange>,
+    /// Chapter titles to download, matched as substrings
+    pub chapters: Vec<String>,
+    /// Re-encode around the cut points for frame-accurate clips (slower)
+    pub precise_cuts: bool,
+}
+
+/// A start/end range such as "1:30" to "4:05"; no end means until the end of the video
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct TimeRange {
+    pub start: String,
+    pub end: Option<String>,
+}
+
+/// Live stream and premiere recording options
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LiveOptions {
+    /// Record from the beginning of the stream instead of from now
+    pub from_start: bool,
+    /// Wait for a scheduled stream or premiere to begin
+    pub wait_for_video: bool,
+    /// Seconds between checks while waiting
+    pub wait_retry_secs: u64,
+}
+
+impl Default for LiveOptions {
+    fn default() -> Self {
+        Self {
+            from_start: false,
+            wait_for_video: true,
+            wait_retry_secs: 60,
+        }
+    }
 }
 
 /// Download Task
@@ -90,8 +430,44 @@ pub struct DownloadTask {
     pub speed: Option<String>,
     pub eta: Option<String>,
     pub error: Option<String>,
+    #[serde(default)]
+    pub downloaded_bytes: Option<u64>,
+    #[serde(default)]
+    pub total_bytes: Option<u64>,
+    /// Name of the running postprocessing stage (e.g. "Merger")
+    #[serde(default)]
+    pub stage: Option<String>,
     pub resolution: String,
+    #[serde(default)]
+    pub options: TaskOptions,
     pub output_path: Option<PathBuf>,
+    /// Automatic retries used since the last manual start
+    #[serde(default)]
+    pub retry_count: u32,
+    /// Recorded duration in seconds for live recordings
+    #[serde(default)]
+    pub elapsed_secs: Option<u64>,
+    /// Per-chapter files written when split_chapters is set
+    #[serde(default)]
+    pub chapter_files: Vec<PathBuf>,
+    /// SponsorBlock segments cut from the file
+    #[serde(default)]
+    pub removed_segments: Vec<SponsorSegment>,
+    /// Total seconds removed by SponsorBlock
+    #[serde(default)]
+    pub time_saved_secs: Option<f64>,
+    /// Higher priority tasks start first, regardless of queue position
+    #[serde(default)]
+    pub priority: i32,
+    /// Position in the queue, 0 is the top
+    #[serde(default)]
+    pub queue_position: u64,
+    /// Destinations and sidecars written by unfinished runs, removed on cancel
+    #[serde(default)]
+    pub temp_files: Vec<PathBuf>,
+    /// Paused when the download window closed, resumed when it opens again
+    #[serde(default)]
+    pub paused_by_schedule: bool,
 }
 
 /// Download Status
@@ -101,12 +477,35 @@ pub enum DownloadStatus {
     Pending,
     Fetching,
     Downloading,
+    /// ffmpeg is merging the video and audio streams
+    Merging,
+    /// Converting, extracting audio or embedding metadata
+    PostProcessing,
+    /// Checking and fixing up the container
+    Verifying,
+    /// Recording a live stream, which has no known total size
+    Recording,
     Paused,
     Completed,
     Failed,
     Cancelled,
 }
 
+impl DownloadStatus {
+    /// Whether a yt-dlp process is running for a task in this status
+    pub fn is_active(&self) -> bool {
+        matches!(
+            self,
+            DownloadStatus::Fetching
+                | DownloadStatus::Downloading
+                | DownloadStatus::Merging
+                | DownloadStatus::PostProcessing
+                | DownloadStatus::Verifying
+                | DownloadStatus::Recording
+        )
+    }
+}
+
 /// yt-dlp Status
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct YtDlpStatus {
@@ -127,6 +526,38 @@ pub struct DownloadProgressEvent {
     pub status: DownloadStatus,
     pub downloaded_bytes: Option<u64>,
     pub total_bytes: Option<u64>,
+    /// Download speed in bytes per second
+    pub speed_bytes: Option<f64>,
+    /// Remaining time in seconds
+    pub eta_secs: Option<u64>,
+    /// Current fragment for HLS/DASH downloads
+    pub fragment_index: Option<u32>,
+    pub fragment_count: Option<u32>,
+    /// Name of the running postprocessing stage
+    pub stage: Option<String>,
+    /// Recorded duration in seconds for live recordings
+    pub elapsed_secs: Option<u64>,
+}
+
+impl DownloadProgressEvent {
+    /// Event with only progress and status set
+    pub fn new(task_id: &str, progress: f64, status: DownloadStatus) -> Self {
+        Self {
+            task_id: task_id.to_string(),
+            progress,
+            speed: None,
+            eta: None,
+            status,
+            downloaded_bytes: None,
+            total_bytes: None,
+            speed_bytes: None,
+            eta_secs: None,
+            fragment_index: None,
+            fragment_count: None,
+            stage: None,
+            elapsed_secs: None,
+        }
+    }
 }
 
 /// Login Status
@@ -151,3 +582,81 @@ pub struct CookiesValidationResult {
     /// User-friendly message
     pub message: String,
 }
+
+/// One video from an expanded playlist or channel
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PlaylistEntry {
+    pub id: String,
+    pub url: String,
+    pub title: Option<String>,
+    pub duration: Option<u64>,
+    /// 1-based position in the playlist
+    pub index: u32,
+    pub playlist_count: Option<u32>,
+    pub playlist_title: Option<String>,
+    /// e.g. "public", "unlisted", "subscriber_only"
+    pub availability: Option<String>,
+    /// e.g. "not_live", "is_live", "is_upcoming", "was_live"
+    pub live_status: Option<String>,
+    /// YYYYMMDD, when the listing provides it
+    pub upload_date: Option<String>,
+    pub thumbnail: Option<String>,
+    pub uploader: Option<String>,
+    pub is_short: bool,
+    /// yt-dlp extractor key, used for the download archive
+    pub extractor: Option<String>,
+}
+
+/// Filters applied when expanding a playlist
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PlaylistFilter {
+    /// yt-dlp item selection, e.g. "1-10,15"
+    pub items: Option<String>,
+    /// Only videos uploaded on or after this date (YYYY-MM-DD)
+    pub date_after: Option<String>,
+    /// Only videos uploaded on or before this date (YYYY-MM-DD)
+    pub date_before: Option<String>,
+    /// Minimum duration in seconds
+    pub min_duration: Option<u64>,
+    /// Maximum duration in seconds
+    pub max_duration: Option<u64>,
+    /// Only videos whose title matches this regex
+    pub title_regex: Option<String>,
+    pub reverse: bool,
+    pub skip_shorts: bool,
+    pub skip_live: bool,
+}
+
+/// A channel or playlist that is checked periodically for new videos
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Subscription {
+    pub id: String,
+    pub url: String,
+    pub name: Option<String>,
+    pub resolution: String,
+    /// Output filename template for this source, overriding the one in settings
+    pub output_template: Option<String>,
+    pub check_interval_mins: u64,
+    pub enabled: bool,
+    /// Unix timestamp of the last check
+    pub last_checked: Option<u64>,
+    /// Queue the videos already in the source on the first sync, instead of
+    /// only the ones that appear after subscribing
+    #[serde(default)]
+    pub backfill: bool,
+    /// Video URLs listed by earlier syncs, None until the first sync
+    #[serde(default)]
+    pub seen: Option<HashSet<String>>,
+}
+
+/// Result of a subscription sync
+#[derive(Debug, Clone, Serialize)]
+pub struct SubscriptionSyncedEvent {
+    pub subscription_id: String,
+    /// Videos not seen by earlier syncs and not in the archive or the queue
+    pub new_count: u32,
+    /// Tasks created and started
+    pub queued_count: u32,
+    pub error: Option<String>,
+}
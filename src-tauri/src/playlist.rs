@@ -0,0 +1,185 @@
+//! Playlist entries and filters for `expand_playlist`
+//!
+//! Entries come from `yt-dlp --flat-playlist --dump-json`, which is fast but
+//! only carries partial metadata. Filters on a field the entry doesn't have
+//! (e.g. no upload date in a flat channel listing) let the entry through.
+
+use crate::models::{PlaylistEntry, PlaylistFilter, VideoInfo};
+use regex::Regex;
+use std::sync::OnceLock;
+
+/// Build an entry from one line of flat-playlist output
+pub fn parse_entry(json: &serde_json::Value, position: u32) -> Option<PlaylistEntry> {
+    let string = |key: &str| json[key].as_str().map(|s| s.to_string());
+    let id = string("id");
+
+    let url = match &id {
+        Some(video_id) => format!("https://www.youtube.com/watch?v={}", video_id),
+        None => string("url")?,
+    };
+    let is_short = json["url"]
+        .as_str()
+        .map(|u| u.contains("/shorts/"))
+        .unwrap_or(false);
+
+    Some(PlaylistEntry {
+        id: id.unwrap_or_default(),
+        url,
+        title: string("title"),
+        duration: json["duration"].as_f64().map(|d| d as u64),
+        index: json["playlist_index"]
+            .as_u64()
+            .map(|i| i as u32)
+            .unwrap_or(position),
+        playlist_count: json["playlist_count"].as_u64().map(|c| c as u32),
+        playlist_title: string("playlist_title"),
+        availability: string("availability"),
+        live_status: string("live_status"),
+        upload_date: string("upload_date").or_else(|| {
+            json["timestamp"]
+                .as_i64()
+                .or(json["release_timestamp"].as_i64())
+                .map(date_from_timestamp)
+        }),
+        thumbnail: json["thumbnails"]
+            .as_array()
+            .and_then(|thumbs| thumbs.last())
+            .and_then(|t| t["url"].as_str())
+            .map(|s| s.to_string()),
+        uploader: string("channel").or_else(|| string("uploader")),
+        is_short,
+        extractor: string("ie_key").or_else(|| string("extractor_key")),
+    })
+}
+
+/// Partial VideoInfo for a task created from a playlist entry
+pub fn entry_video_info(entry: &PlaylistEntry) -> VideoInfo {
+    VideoInfo {
+        id: entry.id.clone(),
+        url: entry.url.clone(),
+        title: entry
+            .title
+            .clone()
+            .unwrap_or_else(|| "Unknown Title".to_string()),
+        duration: entry.duration,
+        duration_string: None,
+        thumbnail: entry.thumbnail.clone(),
+        uploader: entry.uploader.clone(),
+        view_count: None,
+        formats: Vec::new(),
+        playlist_index: Some(entry.index),
+        playlist_count: entry.playlist_count,
+        subtitles: Vec::new(),
+        chapters: Vec::new(),
+    }
+}
+
+/// Check a filter before running yt-dlp, returning the compiled title regex
+pub fn validate_filter(filter: &PlaylistFilter) -> Result<Option<Regex>, String> {
+    // yt-dlp's --playlist-items syntax: `1-10,15`, `-5`, `::2`, `10:`
+    static ITEM_REGEX: OnceLock<Regex> = OnceLock::new();
+    let item_regex =
+        ITEM_REGEX.get_or_init(|| Regex::new(r"^(\d+-\d+|-?\d+|-?\d*:-?\d*(:-?\d+)?)$").unwrap());
+
+    if let Some(items) = &filter.items {
+        if items
+            .split(',')
+            .any(|item| !item_regex.is_match(item.trim()))
+        {
+            return Err(format!("Invalid playlist items: {}", items));
+        }
+    }
+    for date in [&filter.date_after, &filter.date_before]
+        .into_iter()
+        .flatten()
+    {
+        if normalize_date(date).is_none() {
+            return Err(format!("Invalid date (expected YYYY-MM-DD): {}", date));
+        }
+    }
+    if let (Some(min), Some(max)) = (filter.min_duration, filter.max_duration) {
+        if min > max {
+            return Err("Minimum duration is longer than maximum duration".to_string());
+        }
+    }
+
+    filter
+        .title_regex
+        .as_deref()
+        .filter(|pattern| !pattern.is_empty())
+        .map(|pattern| Regex::new(pattern).map_err(|e| format!("Invalid title regex: {}", e)))
+        .transpose()
+}
+
+/// Whether an entry passes the filter (item ranges are applied by yt-dlp)
+pub fn matches(
+    entry: &PlaylistEntry,
+    filter: &PlaylistFilter,
+    title_regex: Option<&Regex>,
+) -> bool {
+    if filter.skip_shorts && entry.is_short {
+        return false;
+    }
+    if filter.skip_live
+        && matches!(
+            entry.live_status.as_deref(),
+            Some("is_live" | "is_upcoming" | "was_live" | "post_live")
+        )
+    {
+        return false;
+    }
+
+    if let Some(date) = entry.upload_date.as_deref() {
+        let after = filter.date_after.as_deref().and_then(normalize_date);
+        let before = filter.date_before.as_deref().and_then(normalize_date);
+        if after.is_some_and(|after| date < after.as_str()) {
+            return false;
+        }
+        if before.is_some_and(|before| date > before.as_str()) {
+            return false;
+        }
+    }
+
+    if let Some(duration) = entry.duration {
+        if filter.min_duration.is_some_and(|min| duration < min) {
+            return false;
+        }
+        if filter.max_duration.is_some_and(|max| duration > max) {
+            return false;
+        }
+    }
+
+    if let (Some(regex), Some(title)) = (title_regex, &entry.title) {
+        if !regex.is_match(title) {
+            return false;
+        }
+    }
+
+    true
+}
+
+/// Accept `YYYY-MM-DD` or `YYYYMMDD`, returning yt-dlp's `YYYYMMDD`
+fn normalize_date(date: &str) -> Option<String> {
+    let digits: String = date.chars().filter(|c| *c != '-').collect();
+    if digits.len() == 8 && digits.chars().all(|c| c.is_ascii_digit()) {
+        Some(digits)
+    } else {
+        None
+    }
+}
+
+/// Convert a Unix timestamp to a `YYYYMMDD` date (UTC)
+fn date_from_timestamp(timestamp: i64) -> String {
+    // Civil-from-days algorithm (Howard Hinnant)
+    let days = timestamp.div_euclid(86400);
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    format!("{:04}{:02}{:02}", year, month, day)
+}
@@ -0,0 +1,292 @@
+//! Download process trees
+//!
+//! yt-dlp starts aria2c and ffmpeg as children, so each download runs in its
+//! own process group (Unix) or job object (Windows) and is signalled as a
+//! whole. Running downloads are recorded in `processes.json` so that
+//! processes left behind by a crashed session can be reaped on the next start.
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::PathBuf;
+use std::process::Command;
+use std::sync::RwLock;
+use std::time::Duration;
+
+#[cfg(target_os = "windows")]
+use std::os::windows::process::CommandExt;
+
+#[cfg(target_os = "windows")]
+const CREATE_NO_WINDOW: u32 = 0x08000000;
+#[cfg(target_os = "windows")]
+const CREATE_NEW_PROCESS_GROUP: u32 = 0x00000200;
+
+/// How long a paused download gets to exit on its own before it is killed
+pub const STOP_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Executables a download can leave running, matched when reaping orphans
+#[cfg(unix)]
+const TOOL_NAMES: &[&str] = &["yt-dlp", "ffmpeg", "aria2c"];
+
+/// Spawn the command in a new process group so the tree can be signalled together
+pub fn configure(cmd: &mut tokio::process::Command) {
+    #[cfg(unix)]
+    cmd.process_group(0);
+    #[cfg(target_os = "windows")]
+    cmd.creation_flags(CREATE_NO_WINDOW | CREATE_NEW_PROCESS_GROUP);
+}
+
+/// A download process and everything it started
+pub struct ProcessTree {
+    /// Also the process group ID, as the process leads its group
+    pid: u32,
+    #[cfg(target_os = "windows")]
+    job: windows::Job,
+}
+
+impl ProcessTree {
+    /// Take charge of a child spawned with `configure`
+    pub fn attach(child: &tokio::process::Child) -> Option<Self> {
+        let pid = child.id()?;
+        Some(Self {
+            pid,
+            #[cfg(target_os = "windows")]
+            job: windows::Job::for_process(child.raw_handle()?)?,
+        })
+    }
+
+    pub fn pid(&self) -> u32 {
+        self.pid
+    }
+
+    /// Ask every process in the tree to stop the way Ctrl+C would, so yt-dlp
+    /// can keep its .part file and ffmpeg can finalize its output
+    pub fn interrupt(&self) {
+        #[cfg(unix)]
+        {
+            let _ = Command::new("kill")
+                .args(["-INT", "--", &format!("-{}", self.pid)])
+                .output();
+        }
+        #[cfg(target_os = "windows")]
+        windows::ctrl_break(self.pid);
+    }
+
+    /// Kill every process in the tree
+    pub fn kill(&self) {
+        #[cfg(unix)]
+        {
+            let _ = Command::new("kill")
+                .args(["-KILL", "--", &format!("-{}", self.pid)])
+                .output();
+        }
+        #[cfg(target_os = "windows")]
+        {
+            self.job.terminate();
+            // Children started before the process joined its job
+            let _ = Command::new("taskkill")
+                .args(["/F", "/T", "/PID", &self.pid.to_string()])
+                .creation_flags(CREATE_NO_WINDOW)
+                .output();
+        }
+    }
+}
+
+/// Running download processes by task ID
+pub struct ProcessTable {
+    path: PathBuf,
+    processes: RwLock<HashMap<String, ProcessTree>>,
+}
+
+impl ProcessTable {
+    /// Load the table, reaping processes recorded by a session that didn't shut down
+    pub fn new(app_data_dir: PathBuf) -> Self {
+        let path = app_data_dir.join("processes.json");
+        let orphans: Vec<u32> = fs::read_to_string(&path)
+            .ok()
+            .and_then(|content| serde_json::from_str(&content).ok())
+            .unwrap_or_default();
+        if !orphans.is_empty() {
+            reap_orphans(&orphans);
+        }
+        let _ = fs::remove_file(&path);
+
+        Self {
+            path,
+            processes: RwLock::new(HashMap::new()),
+        }
+    }
+
+    pub fn insert(&self, task_id: String, tree: ProcessTree) {
+        self.processes.write().unwrap().insert(task_id, tree);
+        self.save();
+    }
+
+    /// Forget a process that has exited, unless the task has started another
+    /// since. Dropping its tree closes the job object on Windows, which kills
+    /// any children still left in it.
+    pub fn remove(&self, task_id: &str, pid: u32) {
+        let removed = {
+            let mut processes = self.processes.write().unwrap();
+            match processes.get(task_id) {
+                Some(tree) if tree.pid() == pid => processes.remove(task_id),
+                _ => None,
+            }
+        };
+        if removed.is_some() {
+            self.save();
+        }
+    }
+
+    /// PID of a task's running process
+    pub fn pid(&self, task_id: &str) -> Option<u32> {
+        self.processes.read().unwrap().get(task_id).map(|t| t.pid())
+    }
+
+    #[cfg(test)]
+    pub fn count(&self) -> usize {
+        self.processes.read().unwrap().len()
+    }
+
+    pub fn interrupt(&self, task_id: &str) {
+        if let Some(tree) = self.processes.read().unwrap().get(task_id) {
+            tree.interrupt();
+        }
+    }
+
+    pub fn kill(&self, task_id: &str) {
+        if let Some(tree) = self.processes.read().unwrap().get(task_id) {
+            tree.kill();
+        }
+    }
+
+    /// Kill a task's process if it is still the one with `pid`
+    pub fn kill_if_running(&self, task_id: &str, pid: u32) {
+        if let Some(tree) = self
+            .processes
+            .read()
+            .unwrap()
+            .get(task_id)
+            .filter(|t| t.pid() == pid)
+        {
+            tree.kill();
+        }
+    }
+
+    fn save(&self) {
+        let pids: Vec<u32> = self
+            .processes
+            .read()
+            .unwrap()
+            .values()
+            .map(|t| t.pid())
+            .collect();
+        if pids.is_empty() {
+            let _ = fs::remove_file(&self.path);
+        } else if let Ok(json) = serde_json::to_string(&pids) {
+            let _ = fs::write(&self.path, json);
+        }
+    }
+}
+
+/// Kill processes left in the recorded process groups. Only yt-dlp, ffmpeg
+/// and aria2c are touched, in case a group ID has been reused since.
+#[cfg(unix)]
+fn reap_orphans(groups: &[u32]) {
+    let Ok(output) = Command::new("ps")
+        .args(["-A", "-o", "pid=,pgid=,comm="])
+        .output()
+    else {
+        return;
+    };
+
+    for line in String::from_utf8_lossy(&output.stdout).lines() {
+        let mut fields = line.split_whitespace();
+        let (Some(pid), Some(pgid), Some(command)) = (fields.next(), fields.next(), fields.next())
+        else {
+            continue;
+        };
+        let Ok(pgid) = pgid.parse::<u32>() else {
+            continue;
+        };
+        if groups.contains(&pgid) && TOOL_NAMES.iter().any(|name| command.contains(name)) {
+            let _ = Command::new("kill").args(["-KILL", pid]).output();
+        }
+    }
+}
+
+/// Job objects are created with kill-on-close, so Windows already ended the
+/// tree when the crashed session's handles were closed
+#[cfg(target_os = "windows")]
+fn reap_orphans(_groups: &[u32]) {}
+
+#[cfg(target_os = "windows")]
+mod windows {
+    use std::ffi::c_void;
+    use std::sync::Mutex;
+    use windows_sys::Win32::Foundation::CloseHandle;
+    use windows_sys::Win32::System::Console::{
+        AttachConsole, FreeConsole, GenerateConsoleCtrlEvent, CTRL_BREAK_EVENT,
+    };
+    use windows_sys::Win32::System::JobObjects::{
+        AssignProcessToJobObject, CreateJobObjectW, JobObjectExtendedLimitInformation,
+        SetInformationJobObject, TerminateJobObject, JOBOBJECT_EXTENDED_LIMIT_INFORMATION,
+        JOB_OBJECT_LIMIT_KILL_ON_JOB_CLOSE,
+    };
+
+    /// Job object that kills its processes when the handle is closed
+    pub struct Job(isize);
+
+    impl Job {
+        pub fn for_process(process: *mut c_void) -> Option<Self> {
+            unsafe {
+                let handle = CreateJobObjectW(std::ptr::null(), std::ptr::null());
+                if handle.is_null() {
+                    return None;
+                }
+                let job = Job(handle as isize);
+
+                let mut info: JOBOBJECT_EXTENDED_LIMIT_INFORMATION = std::mem::zeroed();
+                info.BasicLimitInformation.LimitFlags = JOB_OBJECT_LIMIT_KILL_ON_JOB_CLOSE;
+                let configured = SetInformationJobObject(
+                    handle,
+                    JobObjectExtendedLimitInformation,
+                    &info as *const _ as *const c_void,
+                    std::mem::size_of::<JOBOBJECT_EXTENDED_LIMIT_INFORMATION>() as u32,
+                );
+                if configured == 0 || AssignProcessToJobObject(handle, process) == 0 {
+                    return None;
+                }
+                Some(job)
+            }
+        }
+
+        pub fn terminate(&self) {
+            unsafe {
+                TerminateJobObject(self.0 as *mut c_void, 1);
+            }
+        }
+    }
+
+    impl Drop for Job {
+        fn drop(&mut self) {
+            unsafe {
+                CloseHandle(self.0 as *mut c_void);
+            }
+        }
+    }
+
+    /// Console attachment is per process, so signals are sent one at a time
+    static CONSOLE_LOCK: Mutex<()> = Mutex::new(());
+
+    /// Send CTRL_BREAK to a process group by borrowing its hidden console
+    pub fn ctrl_break(group: u32) {
+        let _guard = CONSOLE_LOCK.lock().unwrap();
+        unsafe {
+            FreeConsole();
+            if AttachConsole(group) != 0 {
+                GenerateConsoleCtrlEvent(CTRL_BREAK_EVENT, group);
+                FreeConsole();
+            }
+        }
+    }
+}
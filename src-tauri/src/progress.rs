@@ -0,0 +1,490 @@
+//! Progress parsing for yt-dlp and aria2c output
+//!
+//! yt-dlp is driven with `--progress-template` so every progress update is a
+//! single JSON object on its own line. aria2c (when used as the external
+//! downloader) prints `--summary-interval` lines which are parsed separately.
+//! Postprocessing stages (merging, fixups, conversions) are detected from
+//! yt-dlp's `[Merger]`-style lines and a postprocess progress template.
+//! When yt-dlp hands a download to ffmpeg (live streams), ffmpeg's own
+//! `size=... time=...` status lines on stderr are parsed instead.
+
+use crate::models::DownloadStatus;
+use regex::Regex;
+use serde::Deserialize;
+use std::sync::OnceLock;
+use tokio::io::{AsyncBufReadExt, AsyncRead, BufReader};
+
+/// Marker that prefixes every templated progress line
+pub const PROGRESS_PREFIX: &str = "[vivid-progress]";
+
+/// Marker that prefixes every templated postprocessor line
+pub const STAGE_PREFIX: &str = "[vivid-stage]";
+
+/// Value for yt-dlp's `--progress-template` option
+pub fn progress_template() -> String {
+    format!("download:{} %(progress)j", PROGRESS_PREFIX)
+}
+
+/// Value for a second `--progress-template`, reporting postprocessor start/finish
+pub fn postprocess_template() -> String {
+    format!(
+        "postprocess:{} %(progress.status)s %(progress.postprocessor)s",
+        STAGE_PREFIX
+    )
+}
+
+/// Postprocessors that yt-dlp announces with a `[Name]` prefix
+const POSTPROCESSORS: &[&str] = &[
+    "Merger",
+    "ExtractAudio",
+    "EmbedThumbnail",
+    "EmbedSubtitle",
+    "Metadata",
+    "ModifyChapters",
+    "SponsorBlock",
+    "SplitChapters",
+    "VideoConvertor",
+    "VideoRemuxer",
+    "ThumbnailsConvertor",
+    "SubtitlesConvertor",
+    "FixupM3u8",
+    "FixupM4a",
+    "FixupStretched",
+    "FixupDuplicateMoov",
+    "FixupTimestamp",
+    "FixupDuration",
+];
+
+/// A postprocessing stage starting or finishing
+#[derive(Debug, Clone, PartialEq)]
+pub struct StageUpdate {
+    pub status: DownloadStatus,
+    /// yt-dlp's postprocessor name, e.g. `Merger`
+    pub name: String,
+    pub finished: bool,
+}
+
+/// Map a postprocessor name to the task status shown while it runs
+fn stage_status(name: &str) -> DownloadStatus {
+    if name == "Merger" {
+        DownloadStatus::Merging
+    } else if name.starts_with("Fixup") {
+        DownloadStatus::Verifying
+    } else {
+        DownloadStatus::PostProcessing
+    }
+}
+
+/// Parse a `[Merger] Merging formats into ...` style line or a postprocess template line
+pub fn parse_stage_line(line: &str) -> Option<StageUpdate> {
+    let line = line.trim();
+
+    if let Some(rest) = line.strip_prefix(STAGE_PREFIX) {
+        let mut parts = rest.split_whitespace();
+        let status = parts.next()?;
+        let name = parts.next()?;
+        if !POSTPROCESSORS.contains(&name) {
+            return None;
+        }
+        return Some(StageUpdate {
+            status: stage_status(name),
+            name: name.to_string(),
+            finished: status == "finished",
+        });
+    }
+
+    let name = line.strip_prefix('[')?.split(']').next()?;
+    if !POSTPROCESSORS.contains(&name) {
+        return None;
+    }
+    Some(StageUpdate {
+        status: stage_status(name),
+        name: name.to_string(),
+        finished: false,
+    })
+}
+
+/// A single progress update, normalized across downloaders
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ProgressUpdate {
+    /// Percentage (0-100), if it can be determined
+    pub percent: Option<f64>,
+    pub downloaded_bytes: Option<u64>,
+    pub total_bytes: Option<u64>,
+    /// Speed in bytes per second
+    pub speed: Option<f64>,
+    /// Remaining time in seconds
+    pub eta: Option<u64>,
+    pub fragment_index: Option<u32>,
+    pub fragment_count: Option<u32>,
+    /// Seconds since this file started downloading
+    pub elapsed: Option<f64>,
+}
+
+/// Fields of yt-dlp's progress dict (`%(progress)j`)
+#[derive(Debug, Deserialize)]
+struct YtDlpProgress {
+    status: Option<String>,
+    downloaded_bytes: Option<f64>,
+    total_bytes: Option<f64>,
+    total_bytes_estimate: Option<f64>,
+    speed: Option<f64>,
+    eta: Option<f64>,
+    fragment_index: Option<f64>,
+    fragment_count: Option<f64>,
+    elapsed: Option<f64>,
+}
+
+/// Parse a line produced by the progress template
+pub fn parse_ytdlp_line(line: &str) -> Option<ProgressUpdate> {
+    let json = line.trim().strip_prefix(PROGRESS_PREFIX)?.trim();
+    let raw: YtDlpProgress = serde_json::from_str(json).ok()?;
+
+    let downloaded_bytes = raw.downloaded_bytes.map(|b| b as u64);
+    let total_bytes = raw
+        .total_bytes
+        .or(raw.total_bytes_estimate)
+        .map(|b| b as u64);
+    let fragment_index = raw.fragment_index.map(|i| i as u32);
+    let fragment_count = raw.fragment_count.map(|c| c as u32);
+
+    let percent = if raw.status.as_deref() == Some("finished") {
+        Some(100.0)
+    } else {
+        match (
+            downloaded_bytes,
+            total_bytes,
+            fragment_index,
+            fragment_count,
+        ) {
+            (Some(done), Some(total), _, _) if total > 0 => {
+                Some((done as f64 / total as f64 * 100.0).min(100.0))
+            }
+            (_, _, Some(index), Some(count)) if count > 0 => {
+                Some((index as f64 / count as f64 * 100.0).min(100.0))
+            }
+            _ => None,
+        }
+    };
+
+    Some(ProgressUpdate {
+        percent,
+        downloaded_bytes,
+        total_bytes,
+        speed: raw.speed,
+        eta: raw.eta.map(|e| e as u64),
+        fragment_index,
+        fragment_count,
+        elapsed: raw.elapsed,
+    })
+}
+
+/// Parse an aria2c summary line, e.g.
+/// `[#2089b0 400.0KiB/33.2MiB(1%) CN:16 DL:115.7KiB ETA:4m51s]`
+pub fn parse_aria2_line(line: &str) -> Option<ProgressUpdate> {
+    static ARIA2_REGEX: OnceLock<Regex> = OnceLock::new();
+    let regex = ARIA2_REGEX.get_or_init(|| {
+        Regex::new(
+            r"\[#\w+\s+([\d.]+\w*)/([\d.]+\w*)\((\d+)%\)(?:\s+CN:\d+)?(?:\s+DL:([\d.]+\w*))?(?:\s+ETA:(\w+))?\]",
+        )
+        .unwrap()
+    });
+
+    let caps = regex.captures(line)?;
+    let downloaded_bytes = parse_size(&caps[1]);
+    let total_bytes = parse_size(&caps[2]);
+
+    let percent = match (downloaded_bytes, total_bytes) {
+        (Some(done), Some(total)) if total > 0 => {
+            Some((done as f64 / total as f64 * 100.0).min(100.0))
+        }
+        _ => caps[3].parse::<f64>().ok(),
+    };
+
+    Some(ProgressUpdate {
+        percent,
+        downloaded_bytes,
+        total_bytes,
+        speed: caps
+            .get(4)
+            .and_then(|m| parse_size(m.as_str()))
+            .map(|b| b as f64),
+        eta: caps.get(5).and_then(|m| parse_duration(m.as_str())),
+        ..Default::default()
+    })
+}
+
+/// Parse an ffmpeg status line, e.g.
+/// `frame= 1234 fps= 30 q=-1.0 size=   10240KiB time=00:01:23.45 bitrate=1000.0kbits/s speed=1x`
+pub fn parse_ffmpeg_line(line: &str) -> Option<ProgressUpdate> {
+    static FFMPEG_REGEX: OnceLock<Regex> = OnceLock::new();
+    let regex = FFMPEG_REGEX.get_or_init(|| {
+        Regex::new(
+            r"size=\s*(\d+)(B|kB|KiB|mB|MiB|GiB)?\s+time=(\d+):(\d{2}):(\d{2}(?:\.\d+)?)(?:\s+bitrate=\s*([\d.]+)kbits/s)?",
+        )
+        .unwrap()
+    });
+
+    let caps = regex.captures(line)?;
+    let size: u64 = caps[1].parse().ok()?;
+    let multiplier = match caps.get(2).map(|m| m.as_str()) {
+        Some("kB" | "KiB") => 1024,
+        Some("mB" | "MiB") => 1024 * 1024,
+        Some("GiB") => 1024 * 1024 * 1024,
+        _ => 1,
+    };
+    let hours: f64 = caps[3].parse().ok()?;
+    let minutes: f64 = caps[4].parse().ok()?;
+    let seconds: f64 = caps[5].parse().ok()?;
+
+    Some(ProgressUpdate {
+        downloaded_bytes: Some(size * multiplier),
+        // kbit/s to bytes per second
+        speed: caps
+            .get(6)
+            .and_then(|m| m.as_str().parse::<f64>().ok())
+            .map(|kbits| kbits * 1000.0 / 8.0),
+        elapsed: Some(hours * 3600.0 + minutes * 60.0 + seconds),
+        ..Default::default()
+    })
+}
+
+/// Reads output split on `\n` or `\r`, since ffmpeg and aria2c rewrite
+/// their status line in place with carriage returns
+pub struct LineReader<R> {
+    reader: BufReader<R>,
+}
+
+impl<R: AsyncRead + Unpin> LineReader<R> {
+    pub fn new(reader: R) -> Self {
+        Self {
+            reader: BufReader::new(reader),
+        }
+    }
+
+    /// Next non-empty line, or None at end of output
+    pub async fn next_line(&mut self) -> Option<String> {
+        let mut line = Vec::new();
+        loop {
+            let available = match self.reader.fill_buf().await {
+                Ok(buf) => buf,
+                Err(_) => return None,
+            };
+            if available.is_empty() {
+                return if line.is_empty() {
+                    None
+                } else {
+                    Some(String::from_utf8_lossy(&line).into_owned())
+                };
+            }
+
+            match available.iter().position(|&b| b == b'\n' || b == b'\r') {
+                Some(pos) => {
+                    line.extend_from_slice(&available[..pos]);
+                    self.reader.consume(pos + 1);
+                    // Skip the empty segment between \r and \n
+                    if !line.is_empty() {
+                        return Some(String::from_utf8_lossy(&line).into_owned());
+                    }
+                }
+                None => {
+                    let len = available.len();
+                    line.extend_from_slice(available);
+                    self.reader.consume(len);
+                }
+            }
+        }
+    }
+}
+
+/// Parse an aria2 size such as `400.0KiB` or `0B` into bytes
+fn parse_size(value: &str) -> Option<u64> {
+    let split = value
+        .find(|c: char| !c.is_ascii_digit() && c != '.')
+        .unwrap_or(value.len());
+    let (number, unit) = value.split_at(split);
+    let number: f64 = number.parse().ok()?;
+
+    let multiplier = match unit {
+        "" | "B" => 1.0,
+        "KiB" => 1024.0,
+        "MiB" => 1024.0 * 1024.0,
+        "GiB" => 1024.0 * 1024.0 * 1024.0,
+        "TiB" => 1024.0 * 1024.0 * 1024.0 * 1024.0,
+        _ => return None,
+    };
+
+    Some((number * multiplier) as u64)
+}
+
+/// Parse an aria2 duration such as `4m51s` or `1h2m` into seconds
+fn parse_duration(value: &str) -> Option<u64> {
+    let mut total = 0;
+    let mut number = String::new();
+
+    for c in value.chars() {
+        if c.is_ascii_digit() {
+            number.push(c);
+            continue;
+        }
+        let n: u64 = number.parse().ok()?;
+        number.clear();
+        total += match c {
+            'h' => n * 3600,
+            'm' => n * 60,
+            's' => n,
+            _ => return None,
+        };
+    }
+
+    if number.is_empty() {
+        Some(total)
+    } else {
+        None
+    }
+}
+
+/// Format bytes per second the way yt-dlp does, e.g. `5.23MiB/s`
+pub fn format_speed(bytes_per_sec: f64) -> String {
+    format!("{}/s", format_bytes(bytes_per_sec))
+}
+
+/// Format a byte count, e.g. `31.76MiB`
+pub fn format_bytes(bytes: f64) -> String {
+    const UNITS: [&str; 5] = ["B", "KiB", "MiB", "GiB", "TiB"];
+    let mut value = bytes;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.2}{}", value, UNITS[unit])
+}
+
+/// Format seconds as `MM:SS` or `H:MM:SS`
+pub fn format_eta(secs: u64) -> String {
+    let (hours, minutes, seconds) = (secs / 3600, (secs % 3600) / 60, secs % 60);
+    if hours > 0 {
+        format!("{}:{:02}:{:02}", hours, minutes, seconds)
+    } else {
+        format!("{:02}:{:02}", minutes, seconds)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ytdlp_progress_line() {
+        let line = r#"[vivid-progress] {"status": "downloading", "downloaded_bytes": 3145728, "total_bytes": 12582912, "tmpfilename": "Video [abc123].f137.mp4.part", "filename": "Video [abc123].f137.mp4", "eta": 6, "speed": 1572864.0, "elapsed": 2.0021, "ctx_id": null, "_eta_str": "00:06", "_speed_str": "1.50MiB/s", "_percent_str": " 25.0%"}"#;
+        let update = parse_ytdlp_line(line).unwrap();
+        assert_eq!(update.percent, Some(25.0));
+        assert_eq!(update.downloaded_bytes, Some(3145728));
+        assert_eq!(update.total_bytes, Some(12582912));
+        assert_eq!(update.speed, Some(1572864.0));
+        assert_eq!(update.eta, Some(6));
+        assert_eq!(update.elapsed, Some(2.0021));
+    }
+
+    #[test]
+    fn ytdlp_fragment_line_uses_estimate() {
+        let line = r#"[vivid-progress] {"status": "downloading", "downloaded_bytes": 1048576, "total_bytes": null, "total_bytes_estimate": 4194304.0, "fragment_index": 3, "fragment_count": 12, "eta": null, "speed": null, "elapsed": 0.5}"#;
+        let update = parse_ytdlp_line(line).unwrap();
+        assert_eq!(update.total_bytes, Some(4194304));
+        assert_eq!(update.percent, Some(25.0));
+        assert_eq!(update.fragment_index, Some(3));
+        assert_eq!(update.fragment_count, Some(12));
+        assert_eq!(update.speed, None);
+    }
+
+    #[test]
+    fn ytdlp_fragment_line_without_sizes() {
+        let line = r#"[vivid-progress] {"status": "downloading", "fragment_index": 30, "fragment_count": 120}"#;
+        assert_eq!(parse_ytdlp_line(line).unwrap().percent, Some(25.0));
+    }
+
+    #[test]
+    fn ytdlp_finished_line() {
+        let line = r#"[vivid-progress] {"status": "finished", "downloaded_bytes": 12582912, "total_bytes": 12582912, "elapsed": 8.1}"#;
+        assert_eq!(parse_ytdlp_line(line).unwrap().percent, Some(100.0));
+    }
+
+    #[test]
+    fn ytdlp_other_lines() {
+        assert_eq!(
+            parse_ytdlp_line("[download] Destination: Video [abc123].f137.mp4"),
+            None
+        );
+        assert_eq!(parse_ytdlp_line("[vivid-progress] NA"), None);
+    }
+
+    #[test]
+    fn aria2_summary_line() {
+        let update =
+            parse_aria2_line("[#2089b0 400.0KiB/33.2MiB(1%) CN:16 DL:115.7KiB ETA:4m51s]").unwrap();
+        assert_eq!(update.downloaded_bytes, Some(409600));
+        assert_eq!(update.total_bytes, Some(34812723));
+        assert_eq!(update.speed, Some(118476.0));
+        assert_eq!(update.eta, Some(291));
+        assert!((update.percent.unwrap() - 1.176).abs() < 0.01);
+    }
+
+    #[test]
+    fn aria2_line_without_speed() {
+        let update = parse_aria2_line("[#9f1c2a 12MiB/12MiB(100%) CN:1]").unwrap();
+        assert_eq!(update.percent, Some(100.0));
+        assert_eq!(update.speed, None);
+        assert_eq!(update.eta, None);
+    }
+
+    #[test]
+    fn aria2_other_lines() {
+        // Before the size is known aria2c prints no percentage
+        assert_eq!(parse_aria2_line("[#2089b0 0B/0B CN:1 DL:0B]"), None);
+        assert_eq!(
+            parse_aria2_line("01/01 12:00:00 [NOTICE] Download complete: video.mp4"),
+            None
+        );
+    }
+
+    #[test]
+    fn sizes() {
+        assert_eq!(parse_size("0B"), Some(0));
+        assert_eq!(parse_size("512"), Some(512));
+        assert_eq!(parse_size("400.0KiB"), Some(409600));
+        assert_eq!(parse_size("1.5MiB"), Some(1572864));
+        assert_eq!(parse_size("2GiB"), Some(2147483648));
+        assert_eq!(parse_size("1.5MB"), None);
+        assert_eq!(parse_size("KiB"), None);
+    }
+
+    #[test]
+    fn durations() {
+        assert_eq!(parse_duration("51s"), Some(51));
+        assert_eq!(parse_duration("4m51s"), Some(291));
+        assert_eq!(parse_duration("1h2m"), Some(3720));
+        assert_eq!(parse_duration("12"), None);
+        assert_eq!(parse_duration("3d"), None);
+    }
+
+    #[test]
+    fn line_reader_splits_carriage_returns() {
+        let output: &[u8] = b"[#2089b0 1.0MiB/4.0MiB(25%) CN:16 DL:1.0MiB ETA:3s]\r[#2089b0 2.0MiB/4.0MiB(50%) CN:16 DL:1.0MiB ETA:2s]\r\n[download] 100%\n";
+        let lines = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap()
+            .block_on(async {
+                let mut reader = LineReader::new(output);
+                let mut lines = Vec::new();
+                while let Some(line) = reader.next_line().await {
+                    lines.push(line);
+                }
+                lines
+            });
+
+        assert_eq!(lines.len(), 3);
+        assert_eq!(parse_aria2_line(&lines[1]).unwrap().percent, Some(50.0));
+        assert_eq!(lines[2], "[download] 100%");
+    }
+}
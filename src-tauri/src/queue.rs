@@ -0,0 +1,131 @@
+//! Queue persistence for the download manager
+//!
+//! Stores every task in `queue.json` next to `settings.json` so the queue
+//! survives an application restart. Changes only mark the queue dirty; a
+//! background writer saves it once they settle, off the async runtime.
+
+use crate::models::{DownloadStatus, DownloadTask};
+use std::collections::HashMap;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::Duration;
+use tokio::sync::Notify;
+
+/// How long the writer waits for further changes before saving
+const SAVE_DELAY: Duration = Duration::from_millis(500);
+
+pub struct QueueStore {
+    path: PathBuf,
+    /// Serializes writers so concurrent downloads don't interleave saves
+    write_lock: Mutex<()>,
+    /// Set when the queue changed since the last save
+    dirty: AtomicBool,
+    /// Wakes the background writer
+    wake: Notify,
+}
+
+impl QueueStore {
+    pub fn new(app_data_dir: PathBuf) -> Self {
+        Self {
+            path: app_data_dir.join("queue.json"),
+            write_lock: Mutex::new(()),
+            dirty: AtomicBool::new(false),
+            wake: Notify::new(),
+        }
+    }
+
+    /// Start the background task that saves `tasks` after changes
+    pub fn spawn_writer(self: Arc<Self>, tasks: Arc<RwLock<HashMap<String, DownloadTask>>>) {
+        tauri::async_runtime::spawn(async move {
+            loop {
+                self.wake.notified().await;
+                // Let a burst of changes settle into one write
+                tokio::time::sleep(SAVE_DELAY).await;
+                if !self.dirty.swap(false, Ordering::SeqCst) {
+                    continue;
+                }
+                let store = self.clone();
+                let tasks = tasks.clone();
+                let _ = tauri::async_runtime::spawn_blocking(move || {
+                    let _ = store.save(&tasks.read().unwrap());
+                })
+                .await;
+            }
+        });
+    }
+
+    /// Ask the background writer to save the queue
+    pub fn request_save(&self) {
+        self.dirty.store(true, Ordering::SeqCst);
+        self.wake.notify_one();
+    }
+
+    /// Save now if there are changes the writer hasn't saved yet
+    pub fn flush(&self, tasks: &HashMap<String, DownloadTask>) {
+        if self.dirty.swap(false, Ordering::SeqCst) {
+            let _ = self.save(tasks);
+        }
+    }
+
+    /// Load the saved queue.
+    /// Tasks that were active when the app exited come back as Paused
+    /// so that resume_download continues from the .part file.
+    /// A queue file that can't be parsed is moved aside to `queue.json.bak`
+    /// rather than overwritten by the next save.
+    pub fn load(&self) -> Vec<DownloadTask> {
+        let content = match fs::read_to_string(&self.path) {
+            Ok(c) => c,
+            Err(_) => return Vec::new(),
+        };
+        let mut tasks: Vec<DownloadTask> = match serde_json::from_str(&content) {
+            Ok(tasks) => tasks,
+            Err(e) => {
+                let backup_path = self.path.with_extension("json.bak");
+                eprintln!(
+                    "Failed to parse {}: {}. Moved it to {}",
+                    self.path.display(),
+                    e,
+                    backup_path.display()
+                );
+                let _ = fs::rename(&self.path, &backup_path);
+                return Vec::new();
+            }
+        };
+
+        for task in tasks.iter_mut() {
+            if task.status.is_active() {
+                task.status = DownloadStatus::Paused;
+            }
+            task.stage = None;
+            task.speed = None;
+            task.eta = None;
+        }
+
+        tasks
+    }
+
+    /// Write the whole queue to disk
+    fn save(&self, tasks: &HashMap<String, DownloadTask>) -> Result<(), String> {
+        let _guard = self.write_lock.lock().unwrap();
+
+        let mut list: Vec<&DownloadTask> = tasks.values().collect();
+        list.sort_by_key(|t| t.queue_position);
+        let json = serde_json::to_string_pretty(&list)
+            .map_err(|e| format!("Failed to serialize queue: {}", e))?;
+
+        if let Some(parent) = self.path.parent() {
+            fs::create_dir_all(parent)
+                .map_err(|e| format!("Failed to create queue directory: {}", e))?;
+        }
+
+        // Write to a temp file first so a crash mid-write can't truncate the queue
+        let temp_path = self.path.with_extension("json.tmp");
+        fs::write(&temp_path, json).map_err(|e| format!("Failed to write queue file: {}", e))?;
+        fs::rename(&temp_path, &self.path)
+            .map_err(|e| format!("Failed to replace queue file: {}", e))?;
+
+        Ok(())
+    }
+}
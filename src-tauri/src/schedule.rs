@@ -0,0 +1,124 @@
+//! Download scheduling
+//!
+//! Tasks can be held until a "start after" time, and the global schedule
+//! limits downloads to a daily window in local time (or blocks them during
+//! one). The download queue only starts tasks while the window is open, and a
+//! background task pauses running downloads when it closes and resumes them
+//! when it opens again.
+
+use crate::cookies;
+use crate::models::{DownloadStatus, DownloadTask, ScheduleOptions};
+use crate::AppState;
+use chrono::{Local, Timelike};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tauri::{AppHandle, Manager};
+
+/// How often waiting tasks and the background task re-check the schedule
+pub const POLL_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Parse "HH:MM" into minutes after midnight
+fn parse_time(time: &str) -> Option<u32> {
+    let (hours, minutes) = time.trim().split_once(':')?;
+    let hours: u32 = hours.parse().ok()?;
+    let minutes: u32 = minutes.parse().ok()?;
+    (hours < 24 && minutes < 60).then_some(hours * 60 + minutes)
+}
+
+pub fn validate_schedule(options: &ScheduleOptions) -> Result<(), String> {
+    for time in [&options.start, &options.end] {
+        if parse_time(time).is_none() {
+            return Err(format!("Invalid schedule time (expected HH:MM): {}", time));
+        }
+    }
+    if options.start.trim() == options.end.trim() {
+        return Err("Schedule start and end times must differ".to_string());
+    }
+    if !matches!(options.mode.as_str(), "allow" | "block") {
+        return Err(format!("Unsupported schedule mode: {}", options.mode));
+    }
+    Ok(())
+}
+
+/// Whether `minute` (after midnight) falls in [start, end), wrapping past midnight
+fn in_window(start: u32, end: u32, minute: u32) -> bool {
+    if start <= end {
+        (start..end).contains(&minute)
+    } else {
+        minute >= start || minute < end
+    }
+}
+
+/// Whether downloads may run right now under the global schedule
+pub fn is_open(options: &ScheduleOptions) -> bool {
+    if !options.enabled {
+        return true;
+    }
+    let (Some(start), Some(end)) = (parse_time(&options.start), parse_time(&options.end)) else {
+        return true;
+    };
+
+    let now = Local::now();
+    let inside = in_window(start, end, now.hour() * 60 + now.minute());
+    match options.mode.as_str() {
+        "block" => !inside,
+        _ => inside,
+    }
+}
+
+/// Whether a queued task's "start after" time has passed
+pub fn is_due(task: &DownloadTask) -> bool {
+    task.options
+        .start_after
+        .is_none_or(|start_after| now_secs() >= start_after)
+}
+
+fn now_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+/// Start the background task that pauses and resumes downloads at the window edges
+pub fn spawn_schedule_loop(app_handle: AppHandle) {
+    tauri::async_runtime::spawn(async move {
+        let mut was_open = true;
+
+        loop {
+            let state = app_handle.state::<AppState>();
+            let settings = state.settings.get();
+            let open = is_open(&settings.schedule);
+
+            if was_open && !open {
+                // Post-processing and recordings are left to finish, pausing them
+                // would corrupt a merge or end the recording
+                for task in state.download.get_all_tasks() {
+                    if matches!(
+                        task.status,
+                        DownloadStatus::Fetching | DownloadStatus::Downloading
+                    ) {
+                        state.download.pause_for_schedule(&task.id);
+                    }
+                }
+            } else if open {
+                // Tasks the user resumed or cancelled meanwhile have lost their
+                // mark. Marks saved before a restart are picked up here too.
+                let cookies_path =
+                    cookies::get_cookies_file_path(&state.settings.get_app_data_dir());
+                for task in state.download.get_all_tasks() {
+                    if task.paused_by_schedule && task.status == DownloadStatus::Paused {
+                        state.download.start_download(
+                            task.id,
+                            settings.clone(),
+                            app_handle.clone(),
+                            cookies_path.clone(),
+                        );
+                    }
+                }
+            }
+            was_open = open;
+
+            tokio::time::sleep(POLL_INTERVAL).await;
+        }
+    });
+}
@@ -0,0 +1,134 @@
+//! SponsorBlock segment marking and removal
+//!
+//! yt-dlp fetches the segments from the SponsorBlock API and marks them as
+//! chapters or cuts them out with ffmpeg. The segments it found are written
+//! to the `.info.json` as `sponsorblock_chapters`, which is where the removed
+//! segments reported on the task come from.
+
+use crate::models::{AppSettings, DownloadTask, SponsorBlockOptions, SponsorSegment};
+
+/// Categories yt-dlp accepts for both marking and removal
+const CATEGORIES: &[&str] = &[
+    "sponsor",
+    "intro",
+    "outro",
+    "selfpromo",
+    "preview",
+    "filler",
+    "interaction",
+    "music_offtopic",
+    "all",
+];
+
+/// Categories that can only be marked, since they are points or whole chapters
+const MARK_ONLY_CATEGORIES: &[&str] = &["poi_highlight", "chapter"];
+
+/// Public SponsorBlock API
+pub const DEFAULT_API: &str = "https://sponsor.ajay.app";
+
+/// Effective SponsorBlock options for a task
+pub fn sponsorblock_options(task: &DownloadTask, settings: &AppSettings) -> SponsorBlockOptions {
+    task.options
+        .sponsorblock
+        .clone()
+        .unwrap_or_else(|| settings.default_sponsorblock.clone())
+}
+
+/// Whether the options ask for any SponsorBlock processing
+pub fn is_enabled(options: &SponsorBlockOptions) -> bool {
+    !options.mark.is_empty() || !options.remove.is_empty()
+}
+
+/// yt-dlp arguments for marking and removing segments
+pub fn sponsorblock_args(options: &SponsorBlockOptions, api: &str) -> Vec<String> {
+    if !is_enabled(options) {
+        return Vec::new();
+    }
+
+    let mut args = Vec::new();
+    if !options.mark.is_empty() {
+        args.push("--sponsorblock-mark".to_string());
+        args.push(options.mark.join(","));
+    }
+    if !options.remove.is_empty() {
+        args.push("--sponsorblock-remove".to_string());
+        args.push(options.remove.join(","));
+    }
+    if !api.is_empty() {
+        args.push("--sponsorblock-api".to_string());
+        args.push(api.trim_end_matches('/').to_string());
+    }
+
+    args
+}
+
+/// Check that every category is one yt-dlp knows
+pub fn validate_sponsorblock(options: &SponsorBlockOptions) -> Result<(), String> {
+    for category in &options.mark {
+        if !CATEGORIES.contains(&category.as_str())
+            && !MARK_ONLY_CATEGORIES.contains(&category.as_str())
+        {
+            return Err(format!("Unknown SponsorBlock category: {}", category));
+        }
+    }
+    for category in &options.remove {
+        if MARK_ONLY_CATEGORIES.contains(&category.as_str()) {
+            return Err(format!(
+                "SponsorBlock category {} can only be marked",
+                category
+            ));
+        }
+        if !CATEGORIES.contains(&category.as_str()) {
+            return Err(format!("Unknown SponsorBlock category: {}", category));
+        }
+    }
+    Ok(())
+}
+
+/// Check that the API endpoint is an http(s) URL
+pub fn validate_api(api: &str) -> Result<(), String> {
+    if api.starts_with("http://") || api.starts_with("https://") {
+        Ok(())
+    } else {
+        Err(format!("Invalid SponsorBlock API URL: {}", api))
+    }
+}
+
+/// Segments removed from a download and the total time they covered,
+/// read from the `sponsorblock_chapters` of a yt-dlp info dict
+pub fn removed_segments(
+    json: &serde_json::Value,
+    options: &SponsorBlockOptions,
+) -> (Vec<SponsorSegment>, f64) {
+    let remove_all = options.remove.iter().any(|c| c == "all");
+    let mut segments: Vec<SponsorSegment> = json["sponsorblock_chapters"]
+        .as_array()
+        .map(|chapters| {
+            chapters
+                .iter()
+                .filter_map(|c| {
+                    Some(SponsorSegment {
+                        category: c["category"].as_str()?.to_string(),
+                        start_time: c["start_time"].as_f64()?,
+                        end_time: c["end_time"].as_f64()?,
+                    })
+                })
+                .filter(|s| remove_all || options.remove.contains(&s.category))
+                .collect()
+        })
+        .unwrap_or_default();
+    segments.sort_by(|a, b| a.start_time.total_cmp(&b.start_time));
+
+    // Overlapping segments are only cut once
+    let mut saved = 0.0;
+    let mut covered_until = f64::MIN;
+    for segment in &segments {
+        let start = segment.start_time.max(covered_until);
+        if segment.end_time > start {
+            saved += segment.end_time - start;
+        }
+        covered_until = covered_until.max(segment.end_time);
+    }
+
+    (segments, saved)
+}
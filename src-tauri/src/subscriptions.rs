@@ -0,0 +1,279 @@
+//! Channel and playlist subscriptions
+//!
+//! Each subscription is re-expanded on its own interval by a background task.
+//! The first sync records the videos already in the source, unless backfill
+//! is set. After that, videos not seen before that are not in the download
+//! archive and not already queued become new tasks, which are started
+//! immediately.
+
+use crate::cookies;
+use crate::models::{
+    PlaylistEntry, PlaylistFilter, Subscription, SubscriptionSyncedEvent, TaskOptions,
+};
+use crate::template;
+use crate::AppState;
+use std::collections::HashSet;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::RwLock;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tauri::{AppHandle, Emitter, Manager};
+use uuid::Uuid;
+
+/// How often the background task looks for due subscriptions
+const POLL_INTERVAL: Duration = Duration::from_secs(60);
+
+pub struct SubscriptionManager {
+    path: PathBuf,
+    subscriptions: RwLock<Vec<Subscription>>,
+}
+
+impl SubscriptionManager {
+    pub fn new(app_data_dir: PathBuf) -> Self {
+        let path = app_data_dir.join("subscriptions.json");
+        let subscriptions = fs::read_to_string(&path)
+            .ok()
+            .and_then(|content| serde_json::from_str(&content).ok())
+            .unwrap_or_default();
+
+        Self {
+            path,
+            subscriptions: RwLock::new(subscriptions),
+        }
+    }
+
+    pub fn get_all(&self) -> Vec<Subscription> {
+        self.subscriptions.read().unwrap().clone()
+    }
+
+    pub fn get(&self, id: &str) -> Option<Subscription> {
+        self.subscriptions
+            .read()
+            .unwrap()
+            .iter()
+            .find(|s| s.id == id)
+            .cloned()
+    }
+
+    pub fn add(
+        &self,
+        url: String,
+        name: Option<String>,
+        resolution: String,
+        output_template: Option<String>,
+        check_interval_mins: u64,
+        backfill: bool,
+    ) -> Result<Subscription, String> {
+        let subscription = Subscription {
+            id: Uuid::new_v4().to_string(),
+            url,
+            name,
+            resolution,
+            output_template,
+            check_interval_mins,
+            enabled: true,
+            last_checked: None,
+            backfill,
+            seen: None,
+        };
+        Self::validate(&subscription)?;
+
+        self.subscriptions
+            .write()
+            .unwrap()
+            .push(subscription.clone());
+        self.save()?;
+        Ok(subscription)
+    }
+
+    /// Update a subscription's settings. The seen videos are kept.
+    pub fn update(&self, mut subscription: Subscription) -> Result<(), String> {
+        Self::validate(&subscription)?;
+        {
+            let mut subscriptions = self.subscriptions.write().unwrap();
+            let existing = subscriptions
+                .iter_mut()
+                .find(|s| s.id == subscription.id)
+                .ok_or_else(|| "Subscription not found".to_string())?;
+            subscription.seen = existing.seen.take();
+            *existing = subscription;
+        }
+        self.save()
+    }
+
+    pub fn remove(&self, id: &str) -> Result<(), String> {
+        self.subscriptions.write().unwrap().retain(|s| s.id != id);
+        self.save()
+    }
+
+    /// Subscriptions whose check interval has elapsed
+    pub fn due(&self) -> Vec<Subscription> {
+        let now = now_secs();
+        self.subscriptions
+            .read()
+            .unwrap()
+            .iter()
+            .filter(|s| s.enabled)
+            .filter(|s| match s.last_checked {
+                Some(last) => now >= last + s.check_interval_mins * 60,
+                None => true,
+            })
+            .cloned()
+            .collect()
+    }
+
+    fn mark_checked(&self, id: &str) {
+        if let Some(s) = self
+            .subscriptions
+            .write()
+            .unwrap()
+            .iter_mut()
+            .find(|s| s.id == id)
+        {
+            s.last_checked = Some(now_secs());
+        }
+        let _ = self.save();
+    }
+
+    /// Add video URLs to the ones a subscription has seen
+    fn mark_seen(&self, id: &str, urls: impl IntoIterator<Item = String>) {
+        if let Some(s) = self
+            .subscriptions
+            .write()
+            .unwrap()
+            .iter_mut()
+            .find(|s| s.id == id)
+        {
+            s.seen.get_or_insert_with(HashSet::new).extend(urls);
+        }
+        let _ = self.save();
+    }
+
+    fn validate(subscription: &Subscription) -> Result<(), String> {
+        if subscription.url.trim().is_empty() {
+            return Err("Subscription URL cannot be empty".to_string());
+        }
+        if subscription.check_interval_mins == 0 {
+            return Err("Check interval must be at least 1 minute".to_string());
+        }
+        if let Some(output_template) = &subscription.output_template {
+            template::validate_template(output_template)?;
+        }
+        Ok(())
+    }
+
+    fn save(&self) -> Result<(), String> {
+        let json = serde_json::to_string_pretty(&*self.subscriptions.read().unwrap())
+            .map_err(|e| format!("Failed to serialize subscriptions: {}", e))?;
+        fs::write(&self.path, json).map_err(|e| format!("Failed to write subscriptions: {}", e))
+    }
+}
+
+fn now_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+/// Re-expand a subscription and queue its new videos. Blocks on yt-dlp.
+pub fn sync_subscription(app_handle: &AppHandle, id: &str) -> SubscriptionSyncedEvent {
+    let state = app_handle.state::<AppState>();
+    let mut event = SubscriptionSyncedEvent {
+        subscription_id: id.to_string(),
+        new_count: 0,
+        queued_count: 0,
+        error: None,
+    };
+
+    let Some(subscription) = state.subscriptions.get(id) else {
+        event.error = Some("Subscription not found".to_string());
+        return event;
+    };
+
+    let settings = state.settings.get();
+    let cookies_path = cookies::get_cookies_file_path(&state.settings.get_app_data_dir());
+
+    // Checked even on failure so a broken source isn't retried every poll
+    state.subscriptions.mark_checked(id);
+
+    // expand_playlist already drops videos that are in the download archive
+    let entries = match state.download.expand_playlist(
+        &subscription.url,
+        &cookies_path,
+        &PlaylistFilter::default(),
+    ) {
+        Ok(entries) => entries,
+        Err(e) => {
+            event.error = Some(e);
+            let _ = app_handle.emit("subscription-synced", &event);
+            return event;
+        }
+    };
+
+    // The first sync only records what is already there, unless backfilling
+    let seen = match &subscription.seen {
+        Some(seen) => seen.clone(),
+        None if subscription.backfill => HashSet::new(),
+        None => entries.iter().map(|entry| entry.url.clone()).collect(),
+    };
+    state
+        .subscriptions
+        .mark_seen(id, entries.iter().map(|entry| entry.url.clone()));
+
+    // Skip videos that are still sitting in the queue from an earlier sync
+    let queued: HashSet<String> = state
+        .download
+        .get_all_tasks()
+        .into_iter()
+        .map(|t| t.url)
+        .collect();
+    let new_entries: Vec<PlaylistEntry> = entries
+        .into_iter()
+        .filter(|entry| !seen.contains(&entry.url) && !queued.contains(&entry.url))
+        .collect();
+    event.new_count = new_entries.len() as u32;
+
+    for entry in new_entries {
+        let options = TaskOptions {
+            output_template: subscription.output_template.clone(),
+            ..Default::default()
+        };
+        let task = match state.download.create_task_from_entry(
+            &entry,
+            subscription.resolution.clone(),
+            options,
+        ) {
+            Ok(task) => task,
+            Err(_) => continue,
+        };
+        state.download.start_download(
+            task.id,
+            settings.clone(),
+            app_handle.clone(),
+            cookies_path.clone(),
+        );
+        event.queued_count += 1;
+    }
+
+    let _ = app_handle.emit("subscription-synced", &event);
+    event
+}
+
+/// Start the background task that syncs due subscriptions
+pub fn spawn_sync_loop(app_handle: AppHandle) {
+    tauri::async_runtime::spawn(async move {
+        loop {
+            let due = app_handle.state::<AppState>().subscriptions.due();
+            for subscription in due {
+                let app_handle = app_handle.clone();
+                let _ = tauri::async_runtime::spawn_blocking(move || {
+                    sync_subscription(&app_handle, &subscription.id)
+                })
+                .await;
+            }
+
+            tokio::time::sleep(POLL_INTERVAL).await;
+        }
+    });
+}
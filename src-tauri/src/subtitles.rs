@@ -0,0 +1,102 @@
+//! Subtitle and auto-caption options for yt-dlp
+
+use crate::models::{AppSettings, DownloadTask, SubtitleOptions, SubtitleTrack};
+
+/// Subtitle formats yt-dlp can convert to
+const SUBTITLE_FORMATS: &[&str] = &["srt", "vtt", "ass"];
+
+/// Effective subtitle options for a task
+pub fn subtitle_options(task: &DownloadTask, settings: &AppSettings) -> SubtitleOptions {
+    task.options
+        .subtitles
+        .clone()
+        .unwrap_or_else(|| settings.default_subtitles.clone())
+}
+
+/// Build the subtitle arguments for a download.
+/// Converting and embedding need ffmpeg; without it the original format is kept.
+/// Audio-only downloads can't carry subtitle streams, so they get sidecar files.
+pub fn subtitle_args(options: &SubtitleOptions, audio_only: bool, has_ffmpeg: bool) -> Vec<String> {
+    if !options.enabled || (!options.manual && !options.auto_generated) {
+        return Vec::new();
+    }
+
+    let mut args = Vec::new();
+    if options.manual {
+        args.push("--write-subs".to_string());
+    }
+    if options.auto_generated {
+        args.push("--write-auto-subs".to_string());
+    }
+
+    let languages = if options.languages.is_empty() {
+        "all".to_string()
+    } else {
+        options.languages.join(",")
+    };
+    args.push("--sub-langs".to_string());
+    args.push(languages);
+
+    args.push("--sub-format".to_string());
+    args.push(format!("{}/best", options.format));
+    if !has_ffmpeg {
+        return args;
+    }
+    args.push("--convert-subs".to_string());
+    args.push(options.format.clone());
+
+    if options.embed && !audio_only {
+        args.push("--embed-subs".to_string());
+    }
+
+    args
+}
+
+/// Check that subtitle options name a supported format
+pub fn validate_subtitles(options: &SubtitleOptions) -> Result<(), String> {
+    if !SUBTITLE_FORMATS.contains(&options.format.as_str()) {
+        return Err(format!("Unsupported subtitle format: {}", options.format));
+    }
+    if options
+        .languages
+        .iter()
+        .any(|lang| lang.trim().is_empty() || lang.contains(','))
+    {
+        return Err("Subtitle languages must be non-empty codes without commas".to_string());
+    }
+    Ok(())
+}
+
+/// List the subtitle tracks in a yt-dlp info dict, manual ones first
+pub fn parse_tracks(json: &serde_json::Value) -> Vec<SubtitleTrack> {
+    let mut tracks = Vec::new();
+
+    for (key, auto_generated) in [("subtitles", false), ("automatic_captions", true)] {
+        let Some(languages) = json[key].as_object() else {
+            continue;
+        };
+
+        for (language, entries) in languages {
+            // yt-dlp lists live chat replay as a subtitle track
+            if language == "live_chat" {
+                continue;
+            }
+            let entries = entries.as_array().cloned().unwrap_or_default();
+            tracks.push(SubtitleTrack {
+                language: language.clone(),
+                name: entries
+                    .iter()
+                    .find_map(|e| e["name"].as_str())
+                    .map(|s| s.to_string()),
+                formats: entries
+                    .iter()
+                    .filter_map(|e| e["ext"].as_str())
+                    .map(|s| s.to_string())
+                    .collect(),
+                auto_generated,
+            });
+        }
+    }
+
+    tracks
+}
@@ -0,0 +1,91 @@
+//! Output filename templates
+//!
+//! Users write templates with `{placeholder}` names such as
+//! `{uploader}/{playlist_title}/{playlist_index} - {title}.{ext}`, which are
+//! translated into yt-dlp's `%(field)s` output template syntax. The template
+//! is relative to the download directory and may contain `/` to create
+//! subfolders.
+
+use crate::models::{AppSettings, DownloadTask};
+
+/// Template used when none is configured
+pub const DEFAULT_TEMPLATE: &str = "{title}.{ext}";
+
+/// Supported placeholders and the yt-dlp fields they expand to.
+/// Playlist fields are empty for single videos.
+const PLACEHOLDERS: &[(&str, &str)] = &[
+    ("title", "%(title)s"),
+    ("id", "%(id)s"),
+    ("ext", "%(ext)s"),
+    ("uploader", "%(uploader|Unknown)s"),
+    ("channel", "%(channel|Unknown)s"),
+    ("playlist_title", "%(playlist_title|)s"),
+    ("playlist_index", "%(playlist_index|)s"),
+    ("upload_date", "%(upload_date>%Y-%m-%d|Unknown)s"),
+    ("resolution", "%(resolution)s"),
+];
+
+/// Effective template for a task
+pub fn task_template(task: &DownloadTask, settings: &AppSettings) -> String {
+    task.options
+        .output_template
+        .clone()
+        .unwrap_or_else(|| settings.output_template.clone())
+}
+
+/// Translate a template into a yt-dlp output template.
+/// `.{ext}` is appended if the template doesn't place the extension itself.
+pub fn to_ytdlp(template: &str) -> Result<String, String> {
+    let template = template.trim();
+    let mut output = String::new();
+    let mut chars = template.chars();
+    let mut has_ext = false;
+
+    while let Some(c) = chars.next() {
+        match c {
+            '{' => {
+                let name: String = chars.by_ref().take_while(|&c| c != '}').collect();
+                let field = PLACEHOLDERS
+                    .iter()
+                    .find(|(placeholder, _)| *placeholder == name)
+                    .map(|(_, field)| *field)
+                    .ok_or_else(|| format!("Unknown placeholder in template: {{{}}}", name))?;
+                has_ext |= name == "ext";
+                output.push_str(field);
+            }
+            '}' => return Err("Unmatched '}' in template".to_string()),
+            // yt-dlp treats % as the start of a field
+            '%' => output.push_str("%%"),
+            '\\' => output.push('/'),
+            c => output.push(c),
+        }
+    }
+
+    if !has_ext {
+        output.push_str(".%(ext)s");
+    }
+
+    Ok(output)
+}
+
+/// Check that a template is well-formed and stays inside the download directory
+pub fn validate_template(template: &str) -> Result<(), String> {
+    let trimmed = template.trim();
+    if trimmed.is_empty() {
+        return Err("Output template cannot be empty".to_string());
+    }
+    if trimmed.starts_with(['/', '\\']) || trimmed.chars().nth(1) == Some(':') {
+        return Err("Output template must be relative to the download folder".to_string());
+    }
+    if trimmed.split(['/', '\\']).any(|part| part.trim() == "..") {
+        return Err("Output template cannot contain '..'".to_string());
+    }
+    if trimmed.ends_with(['/', '\\']) {
+        return Err("Output template must end with a file name".to_string());
+    }
+    if trimmed.matches('{').count() != trimmed.matches('}').count() {
+        return Err("Unbalanced braces in template".to_string());
+    }
+
+    to_ytdlp(trimmed).map(|_| ())
+}